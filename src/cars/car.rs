@@ -1,17 +1,21 @@
-use std::rc::Rc;
 use rand::Rng;
 
-use sdl2::{pixels::Color, rect::{Point, Rect}};
-use crate::entities::*;
-use crate::map::TrafficLight;
-use sdl2::render::Texture;
+use crate::geometry::{Obb, Path, Point, Rect, Vec2};
+use crate::map::{Crossing, Lane, LaneChange, Route};
+use super::{Dynamics, Idm, Leader};
 
 
-const BASE_VELOCITY: u32 = 4;
-const SLOW_VELOCITY: u32 = 2;  // Reduced speed for safety
-const SAFE_DISTANCE: u32 = 40; // Increased safe distance
-const TURN_VELOCITY: u32 = 2;  // Slower speed for turns
-const DETECTION_OFFSET: i32 = 10; // Increased detection offset
+// Velocities are in pixels per second of simulated time
+pub const BASE_VELOCITY: f64 = 240.0;
+/// Pixels added to the car's length and width to size the cross traffic probe.
+const SAFE_DISTANCE: u32 = 40;
+pub const TURN_VELOCITY: f64 = 120.0;  // Slower speed for turns
+/// Sideways shift of the cross traffic probe towards the car's right, in pixels.
+const DETECTION_OFFSET: f64 = 10.0;
+const STOP_MARGIN: f64 = 2.0; // Stop this far before the stop line
+const LEADER_LOOKAHEAD: f64 = 400.0; // How far along the path we look for a leader
+const LANE_TOLERANCE: f64 = 12.0; // Lateral distance under which a car is on our path
+const STOPPED_SPEED: f64 = 1.0; // Under this speed a car counts as stopped
 
 const TRECTS: [(&str,i32,i32,u32,u32);12] = 
 [
@@ -29,19 +33,15 @@ const TRECTS: [(&str,i32,i32,u32,u32);12] =
     ("challenger3",145,138,28,48)
 ];
 
-// #[derive(Debug,Clone)]
-pub enum DisplayType<'a> {
-    Texture(Rc<&'a Texture<'a>>),
-    Color(Color),
-}
+/// Plain colour, red, green and blue.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
 
-impl Clone for DisplayType<'_> {
-    fn clone(&self) -> Self {
-       match *self {
-           DisplayType::Texture(ref t) => DisplayType::Texture(Rc::clone(t)),
-           DisplayType::Color(c) => DisplayType::Color(c),
-       }
-    }
+/// How a car is drawn: a region of the sprite atlas or a plain colour.
+#[derive(Debug,Clone,Copy)]
+pub enum DisplayType {
+    Texture(Rect),
+    Color(Rgb),
 }
 
 #[derive(Debug,Clone,PartialEq, Eq)]
@@ -54,336 +54,411 @@ pub enum UpdateState {
     Waiting,
 }
 
-#[derive(Debug,PartialEq,Clone, Copy)]
-pub enum Direction {
-    North,
-    South,
-    West,
-    East,
-}
-
 #[derive(Clone)]
-pub struct Car<'a> {
+pub struct Car {
+    id: u32,
+
+    // Kinematics, the hit box is derived from them
+    position: Vec2,
+    prev_position: Vec2,
+    heading: f64, // radians, 0 = +x
+    speed: f64,
+    dynamics: Dynamics,
+    idm: Idm,
+
     // Boxs
-    hit_box: Rect,
-    detection_lower: Rect,
-    detection_upper: Rect,
+    hit_box: Rect, // Bounding box of `obb`, for broad phase and drawing
+    obb: Obb,
+    probe: Obb, // Cross traffic in the junction in here makes us wait before entering it
+    leader: Option<Vec2>, // Point we are following, for the debug overlay
+    following: Option<u32>, // Car we are following, if one is what we adjust our speed to
 
     // Visual
-    sprite: DisplayType<'a>,
-    t_rect: Option<Rect>,
+    sprite: DisplayType,
 
     // Logic
     state: UpdateState,
-    velocity: u32,
     w_l: (u32,u32),
-    path: Vec<Point>,
-    current_direction: Direction,
+    path: Path,
+    travelled: f64, // Arc length driven along `path`
 
-    debug: bool,
-    current_light: Option<Point>, // Track the first traffic light encountered
+    crossings: Vec<Crossing>, // Where our path goes through intersections, in order
+    lane_changes: Vec<LaneChange>, // Where our path moves to another lane, put off while the gap is too small
+    held: bool, // Kept at the next stop line by its intersection controller
+    yield_to: Option<(f64,u32)>, // Arc length to stop at in the junction, before a car sitting on our way
 }
 
-impl<'a> From<&'a Texture<'a>> for DisplayType<'a> {
-    fn from(texture: &'a Texture<'a>) -> Self {
-        DisplayType::Texture(Rc::new(texture))
+impl From<Rgb> for DisplayType {
+    fn from(c: Rgb) -> Self {
+        DisplayType::Color(c)
     }
 }
 
-impl<'a> From<Color> for DisplayType<'a> {
-    fn from(c: Color) -> Self {
-        DisplayType::Color(c)
+impl Car {
+    pub fn new<T: Into<DisplayType>>(center: Point, w: u32, l: u32, sprite: T) -> Self {
+        let obb = Obb::new(Vec2::from(center), std::f64::consts::FRAC_PI_2, l as f64, w as f64);
+        let hit_box = obb.aabb();
+        Self {
+            id: 0,
+            position: Vec2::from(center),
+            prev_position: Vec2::from(center),
+            heading: std::f64::consts::FRAC_PI_2,
+            speed: BASE_VELOCITY,
+            dynamics: Dynamics::default(),
+            idm: Idm::default(),
+            hit_box,
+            w_l: (w,l),
+            sprite: sprite.into(),
+            state: UpdateState::Moving,
+            path: Path::default(),
+            travelled: 0.0,
+            obb,
+            probe: obb,
+            leader: None,
+            following: None,
+            crossings: Vec::new(),
+            lane_changes: Vec::new(),
+            held: false,
+            yield_to: None,
+        }
     }
-}
 
+    /// Put the car at the start of the route, facing along it.
+    pub fn set_route(&mut self,route: Route) {
+        self.path = route.path;
+        self.crossings = route.crossings;
+        self.lane_changes = route.lane_changes;
+        self.travelled = 0.0;
+        self.position = self.path.point_at(0.0);
+        self.prev_position = self.position;
+        self.heading = self.path.heading_at(0.0);
+        self.update_boxes();
+    }
 
+    pub fn set_id(&mut self,id: u32) {
+        self.id = id;
+    }
 
-impl Direction {
-    pub fn to_angle(&self) -> f64 {
-        match self {
-            Direction::East => 90.0,
-            Direction::South => 0.0,
-            Direction::West => -90.0,
-            Direction::North => 180.0,
-        }
+    pub fn id(&self) -> u32 {
+        self.id
     }
-}
 
+    /// Intersections on our path, in the order they are crossed.
+    pub fn crossings(&self) -> &[Crossing] {
+        &self.crossings
+    }
 
-impl<'a> Car<'a> {
-    pub fn new<T: Into<DisplayType<'a>>>(center: Point, w: u32, l: u32, sprite: T) -> Self {
-        let hit_box = Rect::from_center(center, w, l);
-        Self {
-            hit_box: hit_box,
-            w_l: (w,l),
-            sprite: sprite.into(),
-            velocity: BASE_VELOCITY,
-            state: UpdateState::Moving,
-            path: Vec::new(),
-            detection_lower: hit_box,
-            detection_upper: hit_box,
-            current_direction: Direction::North,
-            debug: false,
-            t_rect: None,
-            current_light: None,
-        }
+    /// Moves to another lane on our path, in order.
+    pub fn lane_changes(&self) -> &[LaneChange] {
+        &self.lane_changes
     }
 
-    pub fn set_path(&mut self,path: Vec<Point>) {
-        self.path = path;
+    /// Distance driven along our path, in pixels.
+    pub fn travelled(&self) -> f64 {
+        self.travelled
     }
 
-    pub fn set_velocity(&mut self,velocity: u32) {
-        self.velocity = velocity;
+    pub fn state(&self) -> &UpdateState {
+        &self.state
     }
 
-    pub fn set_debug(&mut self,b: bool) {
-        self.debug = b;
+    /// Speed along the path, in pixels per second.
+    pub fn speed(&self) -> f64 {
+        self.speed
     }
 
-    fn is_on_right(&self,other: &Self) -> bool{
-        match (self.current_direction,other.current_direction) {
-            (Direction::South,Direction::East) => true,
-            (Direction::East,Direction::North) => true,
-            (Direction::North,Direction::West) => true,
-            (Direction::West,Direction::South) => true,
-            _ => false,
-        }
+    pub fn is_stopped(&self) -> bool {
+        self.speed < STOPPED_SPEED
+    }
+
+    pub fn dynamics(&self) -> Dynamics {
+        self.dynamics
+    }
+
+    pub fn idm(&self) -> Idm {
+        self.idm
+    }
+
+    /// Car we are following, if one is what we adjust our speed to.
+    pub fn following(&self) -> Option<u32> {
+        self.following
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.held
+    }
+
+    /// Keep the car at its next stop line or let it go.
+    pub fn set_held(&mut self, held: bool) {
+        self.held = held;
+    }
+
+    /// Stop with our front at arc length `at` of the path, before car `car` sitting in the
+    /// junction on our way, or drive on.
+    pub fn set_yield_to(&mut self, yield_to: Option<(f64,u32)>) {
+        self.yield_to = yield_to;
+    }
+
+    pub fn set_dynamics(&mut self,dynamics: Dynamics) {
+        self.dynamics = dynamics;
+    }
+
+    pub fn set_idm(&mut self,idm: Idm) {
+        self.idm = idm;
+    }
+
+    pub fn sprite(&self) -> DisplayType {
+        self.sprite
+    }
+
+    pub fn w_l(&self) -> (u32,u32) {
+        self.w_l
+    }
+
+    pub fn hitbox(&self) -> Rect {
+        self.hit_box
+    }
+
+    /// Center to draw the car at, `alpha` of the way between the previous and the current step.
+    pub fn interpolated_center(&self, alpha: f64) -> Point {
+        self.prev_position.lerp(self.position, alpha).to_point()
+    }
+
+    /// Direction of travel in radians, 0 = +x.
+    pub fn heading(&self) -> f64 {
+        self.heading
     }
 
-    pub fn get_detections(&self) -> Option<(Rect,Rect)>{
-        Some((self.detection_lower,self.detection_upper))  
+    /// Footprint rotated with the true heading.
+    pub fn obb(&self) -> Obb {
+        self.obb
     }
 
-    pub fn set_texture(&mut self,texture: &'a Texture<'a>) {
-        self.sprite = DisplayType::from(texture);
-        let i: usize = rand::thread_rng().gen_range(0..TRECTS.len());
+    fn update_boxes(&mut self) {
+        self.obb = Obb::new(self.position, self.heading, self.w_l.1 as f64, self.w_l.0 as f64);
+        self.hit_box = self.obb.aabb();
+    }
+
+    fn collides_with(&self, other: &Self) -> bool {
+        self.hit_box.has_intersection(other.hit_box) && self.obb.intersects(&other.obb)
+    }
+
+    /// Cross traffic probe and the point currently followed, if any.
+    pub fn get_detections(&self) -> (Obb,Option<Point>) {
+        (self.probe, self.leader.map(Vec2::to_point))
+    }
+
+    /// Pick a random sprite from the car atlas instead of a plain colour.
+    pub fn set_texture<R: Rng>(&mut self, rng: &mut R) {
+        let i: usize = rng.gen_range(0..TRECTS.len());
         let v = TRECTS[i];
-        self.t_rect = Some(Rect::new(v.1, v.2, v.3, v.4))
+        self.sprite = DisplayType::Texture(Rect::new(v.1, v.2, v.3, v.4));
     }
 
-    pub fn update(&mut self, others: Vec<Car>, traffic_lights: &[TrafficLight]) -> UpdateState {
-        if self.path.is_empty() {
+    /// Advance the car by `dt` seconds. `others` may contain this car too, it is skipped by id.
+    pub fn update(&mut self, dt: f64, others: &[Car]) -> UpdateState {
+        self.prev_position = self.position;
+        if self.travelled >= self.path.length() {
             self.state = UpdateState::Finished;
             return UpdateState::Finished; // No more points to reach
         }
-    
-        // Check traffic lights only if we haven't entered the intersection
-        if !self.has_entered_intersection() {
-            let light_detection = Rect::from_center(self.hit_box.center(), 100, 100);
-            
-            // Define the coordinates for left-side traffic lights based on car's direction
-            let left_light_pos = match self.current_direction {
-                Direction::North => Point::new(470, 470), // Left light for northbound
-                Direction::South => Point::new(610, 610), // Left light for southbound
-                Direction::East => Point::new(470, 610),  // Left light for eastbound
-                Direction::West => Point::new(610, 470),  // Left light for westbound
-            };
-            
-            match self.current_light {
-                // If we already have a tracked light, check only that one
-                Some(tracked_position) => {
-                    if tracked_position == left_light_pos {
-                        for light in traffic_lights {
-                            if light.position == tracked_position {
-                                if !light.is_green() {
-                                    self.state = UpdateState::Waiting;
-                                    return UpdateState::Waiting;
-                                }
-                                break;
-                            }
-                        }
-                    }
-                },
-                // If we don't have a tracked light yet, only consider the left light
-                None => {
-                    for light in traffic_lights {
-                        if light.position == left_light_pos &&
-                           light_detection.has_intersection(Rect::from_center(light.position, 100, 100)) {
-                            self.current_light = Some(light.position);
-                            if !light.is_green() {
-                                self.state = UpdateState::Waiting;
-                                return UpdateState::Waiting;
-                            }
-                            break;
-                        }
-                    }
-                }
+
+        if others.iter().any(|o| o.id != self.id && self.collides_with(o)) {
+            self.state = UpdateState::Collided;
+            return UpdateState::Collided;
+        }
+
+        // Follow whatever is closest ahead: our stop line if we are held, a car on our path,
+        // or cross traffic in front of us before we enter the intersection
+        let mut leader: Option<(Leader,Vec2,Option<u32>)> = None;
+        let mut follow = |candidate: Leader, at: Vec2, car: Option<u32>| {
+            if leader.is_none_or(|(l,_,_)| candidate.gap < l.gap) {
+                leader = Some((candidate, at, car));
             }
-        } else {
-            // Reset light tracking once we've entered the intersection
-            self.current_light = None;
+        };
+
+        let forward = Vec2::from_angle(self.heading);
+        if let Some(stop) = self.stop_line_ahead() {
+            let gap = stop - self.travelled - self.w_l.1 as f64 / 2.0 - STOP_MARGIN;
+            if gap < LEADER_LOOKAHEAD {
+                follow(Leader { gap, speed: 0.0 }, self.path.point_at(stop), None);
+            }
+        }
+        // Wait before a lane change the gap does not allow yet, and short of a car sitting in
+        // the junction on our way
+        for (at, car) in self.plan_lane_change(others).into_iter().chain(self.yield_to) {
+            let gap = at - self.travelled - self.w_l.1 as f64 / 2.0 - STOP_MARGIN;
+            follow(Leader { gap: gap.max(0.0), speed: 0.0 }, self.path.point_at(at), Some(car));
         }
 
-        let target = self.path[0]; // Prochain point à atteindre
-        let position = self.hit_box.center();
-    
-        let direction = (
-            (target.x - position.x) as f32,
-            (target.y - position.y) as f32,
+        let probe_center = self.position + forward * (self.w_l.1 as f64 * 1.4) + forward.perpendicular() * DETECTION_OFFSET;
+        self.probe = Obb::new(
+            probe_center,
+            self.heading,
+            (self.w_l.1 + SAFE_DISTANCE) as f64,
+            (self.w_l.0 + SAFE_DISTANCE) as f64,
         );
+        let entered = self.has_entered_intersection();
+
+        for other in others.iter().filter(|o| o.id != self.id) {
+            let half_lengths = (self.w_l.1 + other.w_l.1) as f64 / 2.0;
+            if let Some(along) = self.distance_along_path(other) {
+                let other_speed = other.speed * Vec2::from_angle(other.heading).dot(forward);
+                follow(Leader { gap: along - half_lengths, speed: other_speed.max(0.0) }, other.position, Some(other.id));
+                continue;
+            }
 
-        let card_direction = match (direction.0.abs() > direction.1.abs(),direction.0 <= 0.0,direction.1 <= 0.0) {
-            (true,false,_) => Direction::East,
-            (true,true,_) => Direction::West,
-            (false,_,true) => Direction::South,
-            (false,_,false) => Direction::North,
-        };
+            // Keep out of the way of cross traffic already in the junction, who goes first
+            // is up to the intersection controller
+            if !entered && other.has_entered_intersection() && self.probe.intersects(&other.obb) {
+                let ahead = (other.position - self.position).dot(forward);
+                let gap = ahead - (self.w_l.1 + other.w_l.0) as f64 / 2.0;
+                follow(Leader { gap: gap.max(0.0), speed: 0.0 }, other.position, Some(other.id));
+            }
+        }
 
-        self.current_direction = card_direction;
+        // Adjust speed for turns
+        let speed_limit = if entered { TURN_VELOCITY } else { BASE_VELOCITY };
+        let accel = self.idm.acceleration(&self.dynamics, self.speed, speed_limit, leader.map(|(l,_,_)| l));
+        self.leader = leader.map(|(_,at,_)| at);
+        self.following = leader.and_then(|(_,_,car)| car);
 
+        self.speed = self.dynamics.apply(self.speed, accel, dt);
+        self.advance(self.speed * dt);
 
-        let distance = (direction.0.powi(2) + direction.1.powi(2)).sqrt();
-    
-        if distance < self.velocity as f32 {
-            self.hit_box = Rect::from_center(target, self.hit_box.width(), self.hit_box.height());
-            self.path.remove(0);
+        let state = if self.speed < STOPPED_SPEED && accel <= 0.0 {
+            UpdateState::Waiting
+        } else if accel < 0.0 {
+            UpdateState::Slowing
         } else {
-            let normalized = (direction.0 / distance, direction.1 / distance);
-            let movement = (
-                (normalized.0 * self.velocity as f32) as i32,
-                (normalized.1 * self.velocity as f32) as i32,
-            );
-
-            let new_hitbox = if card_direction == Direction::North || card_direction == Direction::South {
-                Rect::from_center(
-                    Point::new(position.x + movement.0, position.y + movement.1),
-                    self.w_l.0,
-                    self.w_l.1,
-                )
-            } else {
-                Rect::from_center(
-                    Point::new(position.x + movement.0, position.y + movement.1),
-                    self.w_l.1,
-                    self.w_l.0,
-                )
-            };
-    
-
-            let angle = direction.1.atan2(direction.0); 
-    
-            let perpendicular = (-angle.sin(), angle.cos()); 
-            let inc = if card_direction == Direction::North || card_direction == Direction::South {
-                new_hitbox.height()
-            } else {
-                new_hitbox.width()
-            };
-            
-            let detection_distance = inc as f32 * 1.4; 
-            let detection_x = position.x as f32 + detection_distance * angle.cos()
-                + DETECTION_OFFSET as f32 * perpendicular.0;
-            let detection_y = position.y as f32 + detection_distance * angle.sin()
-                + DETECTION_OFFSET as f32 * perpendicular.1;
-            
-
-            let ahead_box_lower = Rect::from_center(
-                Point::new(detection_x as i32, detection_y as i32),
-                self.hit_box.width() + SAFE_DISTANCE,
-                self.hit_box.height() + SAFE_DISTANCE,
-            );
-            
-            let detection_distance = inc as f32 * 2.8; 
-            let detection_x = position.x as f32 + detection_distance * angle.cos()
-                + DETECTION_OFFSET as f32 * perpendicular.0;
-            let detection_y = position.y as f32 + detection_distance * angle.sin()
-                + DETECTION_OFFSET as f32 * perpendicular.1;
-
-            let ahead_box_upper = Rect::from_center(
-                Point::new(detection_x as i32, detection_y as i32),
-                self.hit_box.width() + SAFE_DISTANCE,
-                self.hit_box.height() + SAFE_DISTANCE,
-            );
-            self.detection_lower = ahead_box_lower;
-            self.detection_upper = ahead_box_upper;
-            // Check for collisions and maintain safe distance
-            for other in &others {
-                if self.hit_box.has_intersection(other.hit_box) {
-                    self.state = UpdateState::Collided;
-                    return UpdateState::Collided;
-                }
+            UpdateState::Moving
+        };
+        self.state = state.clone();
+        state
+    }
 
-                // Early detection for slowing down - more aggressive
-                if (ahead_box_upper.has_intersection(other.detection_upper) ||
-                    ahead_box_upper.has_intersection(other.detection_lower)) &&
-                    other.state != UpdateState::Slowing {
-                    self.state = UpdateState::Slowing;
-                    self.velocity = SLOW_VELOCITY;
-                    self.hit_box = new_hitbox;
-                    return UpdateState::Slowing;
-                }
-                
-                // Stop and wait if too close to other vehicles
-                if !self.has_entered_intersection() {
-                    let is_too_close = ahead_box_lower.has_intersection(other.get_hitbox()) ||
-                                     ahead_box_upper.has_intersection(other.detection_lower) ||
-                                     ahead_box_upper.has_intersection(other.get_hitbox());
-                    
-                    if is_too_close {
-                        // Allow passing only if other car is waiting and we have right of way
-                        if other.state == UpdateState::Waiting &&
-                           self.is_on_right(other) &&
-                           !ahead_box_lower.has_intersection(other.get_hitbox()) {
-                            // Proceed with caution
-                            self.velocity = SLOW_VELOCITY;
-                            continue;
-                        }
-                        // Otherwise wait
-                        self.state = UpdateState::Waiting;
-                        return UpdateState::Waiting;
-                    }
-                }
+    /// How far ahead of us `other` is along our remaining path, if it is on it.
+    fn distance_along_path(&self, other: &Self) -> Option<f64> {
+        let s = self.position_on_path(other, self.travelled, self.travelled + LEADER_LOOKAHEAD)?;
+        (s > self.travelled).then_some(s - self.travelled)
+    }
 
-                // Adjust speed for turns
-                if self.has_entered_intersection() {
-                    self.velocity = TURN_VELOCITY;
-                }
-                
-            }
-    
-            self.hit_box = new_hitbox; 
+    /// Arc length of `other` along our path, if it is on it between `from` and `to`. Where
+    /// either of us moves over to another lane, it is on it as soon as it overlaps our lane.
+    pub fn position_on_path(&self, other: &Self, from: f64, to: f64) -> Option<f64> {
+        let (s, lateral) = self.path.project(other.position, other.heading, from, to)?;
+        let tolerance = match other.changing_lane() || self.changes_lane_at(s) {
+            true => self.w_l.0 as f64 / 2.0 + other.obb.projected_radius(Vec2::from_angle(self.path.heading_at(s)).perpendicular()),
+            false => LANE_TOLERANCE,
         };
-        self.velocity = BASE_VELOCITY;
+        (lateral < tolerance).then_some(s)
+    }
 
-        self.state = UpdateState::Moving;
-        return UpdateState::Moving;
+    /// Point of our path at arc length `s`.
+    pub fn point_at(&self, s: f64) -> Vec2 {
+        self.path.point_at(s)
     }
 
-    fn has_entered_intersection(&self) -> bool {
-        let intersection_area = Rect::new(470, 470, 140, 140); // Define the intersection bounds
-        self.hit_box.has_intersection(intersection_area)
+    /// Whether the car is moving over to another lane.
+    pub fn changing_lane(&self) -> bool {
+        self.changes_lane_at(self.travelled)
     }
 
-}
+    /// Whether our path at arc length `s` moves over to another lane.
+    fn changes_lane_at(&self, s: f64) -> bool {
+        self.lane_changes.iter().any(|c| s >= c.start && s <= c.start + c.length)
+    }
 
+    /// Put the next lane change off, as far as it can be, while the gap on the new lane is too
+    /// small. Returns where to wait and the car in the way once it cannot be put off anymore.
+    fn plan_lane_change(&mut self, others: &[Car]) -> Option<(f64, u32)> {
+        let i = self.lane_changes.iter().position(|c| self.travelled < c.start)?;
+        let change = self.lane_changes[i];
+        // Decide while we can still stop before the move
+        let ahead = self.dynamics.stopping_distance(self.speed) + self.w_l.1 as f64;
+        if change.start - self.travelled > ahead {
+            return None;
+        }
+        let blocker = self.lane_blocked(change.to, others)?;
+        let start = (self.travelled + ahead).clamp(change.start, change.latest);
+        if start > change.start {
+            self.path = self.path.splice(change.start, start + change.length, change.points(start));
+            self.lane_changes[i].start = start;
+        }
+        (start >= change.latest).then_some((start, blocker))
+    }
 
-impl<'a> Entity for Car<'a> {
-    fn display(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) -> Result<(), Box<dyn std::error::Error>> {
-        if let DisplayType::Color(c) = &self.sprite {
-            canvas.set_draw_color(*c);
-            canvas.fill_rect(self.get_hitbox())?;
-            return Ok(());
+    /// Car on `lane`, ahead of us or behind, too close for us to move over to it now: either of
+    /// us would have to brake harder than comfortably.
+    fn lane_blocked(&self, lane: Lane, others: &[Car]) -> Option<u32> {
+        let dir = lane.direction();
+        let along = |p: Vec2| (p - lane.start).dot(dir);
+        let here = along(self.position);
+        let mut leader: Option<(f64, &Car)> = None;
+        let mut follower: Option<(f64, &Car)> = None;
+        for other in others.iter().filter(|o| o.id != self.id) {
+            let lateral = (other.position - lane.start).dot(dir.perpendicular()).abs();
+            let s = along(other.position);
+            if lateral >= (self.w_l.0 + other.w_l.0) as f64 / 2.0 || Vec2::from_angle(other.heading).dot(dir) < 0.5 || (s - here).abs() > LEADER_LOOKAHEAD {
+                continue;
+            }
+            if s >= here {
+                if leader.is_none_or(|(l, _)| s < l) {
+                    leader = Some((s, other));
+                }
+            } else if follower.is_none_or(|(f, _)| s > f) {
+                follower = Some((s, other));
+            }
+        }
+
+        let gap = |ahead: f64, behind: f64, a: &Car, b: &Car| ahead - behind - (a.w_l.1 + b.w_l.1) as f64 / 2.0;
+        if let Some((s, other)) = leader {
+            let gap = gap(s, here, other, self);
+            let accel = self.idm.acceleration(&self.dynamics, self.speed, BASE_VELOCITY, Some(Leader { gap, speed: other.speed }));
+            if gap < self.idm.min_gap || accel < -self.dynamics.comfortable_decel {
+                return Some(other.id);
+            }
         }
-        if let DisplayType::Texture(texture) = &self.sprite {
-            let angle = self.current_direction.to_angle();
-            if self.debug {
-                canvas.set_draw_color(Color::GREEN);
-                canvas.draw_rect(self.hit_box)?;
+        if let Some((s, other)) = follower {
+            let gap = gap(here, s, self, other);
+            let accel = other.idm.acceleration(&other.dynamics, other.speed, BASE_VELOCITY, Some(Leader { gap, speed: self.speed }));
+            if gap < other.idm.min_gap || accel < -other.dynamics.comfortable_decel {
+                return Some(other.id);
             }
-            canvas.copy_ex(
-                texture, 
-                self.t_rect, 
-                Some(Rect::from_center(self.hit_box.center(),self.w_l.0,self.w_l.1)), 
-                angle, 
-                None, 
-                false, 
-                false
-            )?;
-            return Ok(());
         }
-        Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Texture is not implemented yet")))
+        None
     }
 
-    fn get_hitbox(&self) -> Rect {
-        self.hit_box
+    /// Move `distance` pixels along the path.
+    fn advance(&mut self, distance: f64) {
+        self.travelled += distance;
+        self.position = self.path.point_at(self.travelled);
+        self.heading = self.path.heading_at(self.travelled);
+        self.update_boxes();
+    }
+
+    /// The intersection we are in, or the next one on our path.
+    pub fn crossing(&self) -> Option<Crossing> {
+        let half = self.w_l.1 as f64 / 2.0;
+        self.crossings.iter().find(|c| self.travelled - half < c.exit).copied()
+    }
+
+    /// The next intersection on our path while we have not reached its stop line yet.
+    pub fn next_crossing(&self) -> Option<Crossing> {
+        self.crossing().filter(|_| !self.has_entered_intersection())
+    }
+
+    /// Arc length of our stop line if we are held at it and have not crossed it yet.
+    fn stop_line_ahead(&self) -> Option<f64> {
+        let crossing = self.crossing()?;
+        (self.held && !self.has_entered_intersection() && self.travelled <= crossing.stop).then_some(crossing.stop)
+    }
+
+    /// Whether part of the car is between the stop line and the end of the crossing.
+    pub fn has_entered_intersection(&self) -> bool {
+        let half = self.w_l.1 as f64 / 2.0;
+        self.crossing().is_some_and(|c| self.travelled + half > c.stop && self.travelled - half < c.exit)
     }
-}
\ No newline at end of file
+
+}
+
@@ -0,0 +1,165 @@
+/// Acceleration exponent of the Intelligent Driver Model.
+const IDM_DELTA: i32 = 4;
+
+/// Longitudinal limits of a vehicle, in pixels per second squared.
+#[derive(Debug,Clone,Copy)]
+pub struct Dynamics {
+    /// Strongest acceleration the engine can give.
+    pub max_accel: f64,
+    /// Deceleration used for planned stops (stop lines, queues).
+    pub comfortable_decel: f64,
+    /// Hardest braking possible, only used when something is too close.
+    pub emergency_decel: f64,
+}
+
+impl Default for Dynamics {
+    fn default() -> Self {
+        Self {
+            max_accel: 240.0,
+            comfortable_decel: 360.0,
+            emergency_decel: 1200.0,
+        }
+    }
+}
+
+impl Dynamics {
+    /// The same vehicle with every limit multiplied by `k`, to get some variety between drivers.
+    pub fn scaled(self, k: f64) -> Self {
+        Self {
+            max_accel: self.max_accel * k,
+            comfortable_decel: self.comfortable_decel * k,
+            emergency_decel: self.emergency_decel * k,
+        }
+    }
+
+    /// Distance needed to stop from `speed` braking comfortably, in pixels.
+    pub fn stopping_distance(&self, speed: f64) -> f64 {
+        speed * speed / (2.0 * self.comfortable_decel)
+    }
+
+    /// New speed after accelerating at `accel` for `dt` seconds, within the limits.
+    pub fn apply(&self, speed: f64, accel: f64, dt: f64) -> f64 {
+        let accel = accel.clamp(-self.emergency_decel, self.max_accel);
+        (speed + accel * dt).max(0.0)
+    }
+}
+
+/// What a car is following: the rear of the car ahead on its path, or a stop line.
+#[derive(Debug,Clone,Copy)]
+pub struct Leader {
+    /// Bumper to bumper distance, in pixels.
+    pub gap: f64,
+    /// Speed of the leader along our path, in pixels per second.
+    pub speed: f64,
+}
+
+/// Intelligent Driver Model parameters of one driver.
+#[derive(Debug,Clone,Copy)]
+pub struct Idm {
+    /// Speed on a free road, in pixels per second.
+    pub desired_speed: f64,
+    /// Time gap kept to the leader, in seconds.
+    pub time_headway: f64,
+    /// Bumper to bumper distance kept when stopped, in pixels.
+    pub min_gap: f64,
+}
+
+impl Default for Idm {
+    fn default() -> Self {
+        Self {
+            desired_speed: 240.0,
+            time_headway: 0.4,
+            min_gap: 12.0,
+        }
+    }
+}
+
+impl Idm {
+    /// Acceleration wanted at `speed` behind `leader`, capped to `speed_limit` if lower than
+    /// the desired speed. `a` and `b` come from the vehicle dynamics.
+    pub fn acceleration(&self, dynamics: &Dynamics, speed: f64, speed_limit: f64, leader: Option<Leader>) -> f64 {
+        let a = dynamics.max_accel;
+        let b = dynamics.comfortable_decel;
+        let v0 = self.desired_speed.min(speed_limit);
+        let free_road = 1.0 - (speed / v0).powi(IDM_DELTA);
+        let interaction = match leader {
+            Some(l) => {
+                let closing = speed - l.speed;
+                let desired_gap = self.min_gap + (speed * self.time_headway + speed * closing / (2.0 * (a * b).sqrt())).max(0.0);
+                (desired_gap / l.gap.max(0.1)).powi(2)
+            },
+            None => 0.0,
+        };
+        a * (free_road - interaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_keeps_within_the_limits() {
+        let d = Dynamics::default();
+        assert_eq!(d.apply(100.0, 1e6, 0.1), 100.0 + d.max_accel * 0.1);
+        assert_eq!(d.apply(200.0, -1e6, 0.1), 200.0 - d.emergency_decel * 0.1);
+        assert_eq!(d.apply(10.0, -d.emergency_decel, 1.0), 0.0);
+    }
+
+    #[test]
+    fn braking_comfortably_stops_within_the_stopping_distance() {
+        let d = Dynamics::default();
+        let dt = 1.0 / 60.0;
+        let (mut speed, mut travelled) = (240.0, 0.0);
+        while speed > 0.0 {
+            speed = d.apply(speed, -d.comfortable_decel, dt);
+            travelled += speed * dt;
+        }
+        assert!(travelled <= d.stopping_distance(240.0));
+        assert!(travelled > d.stopping_distance(240.0) - 240.0 * dt);
+    }
+
+    #[test]
+    fn free_road_accelerates_up_to_the_desired_speed() {
+        let (idm, d) = (Idm::default(), Dynamics::default());
+        assert_eq!(idm.acceleration(&d, 0.0, f64::INFINITY, None), d.max_accel);
+        let half = idm.acceleration(&d, idm.desired_speed / 2.0, f64::INFINITY, None);
+        assert!(half > 0.0 && half < d.max_accel);
+        assert!(idm.acceleration(&d, idm.desired_speed, f64::INFINITY, None).abs() < 1e-9);
+        assert!(idm.acceleration(&d, idm.desired_speed * 1.2, f64::INFINITY, None) < 0.0);
+    }
+
+    #[test]
+    fn speed_limit_caps_the_desired_speed() {
+        let (idm, d) = (Idm::default(), Dynamics::default());
+        assert!(idm.acceleration(&d, 120.0, 120.0, None).abs() < 1e-9);
+        assert!(idm.acceleration(&d, 200.0, 120.0, None) < 0.0);
+    }
+
+    #[test]
+    fn approaching_a_stopped_leader_brakes_harder_when_closer() {
+        let (idm, d) = (Idm::default(), Dynamics::default());
+        let accel = |gap| idm.acceleration(&d, 200.0, f64::INFINITY, Some(Leader { gap, speed: 0.0 }));
+        assert!(accel(1000.0) > 0.0);
+        assert!(accel(100.0) < 0.0);
+        assert!(accel(20.0) < accel(100.0));
+        assert!(accel(20.0) < -d.comfortable_decel);
+    }
+
+    #[test]
+    fn following_at_the_same_speed_keeps_more_than_the_desired_gap() {
+        let (idm, d) = (Idm::default(), Dynamics::default());
+        let speed = 120.0;
+        let follow = |gap| idm.acceleration(&d, speed, f64::INFINITY, Some(Leader { gap, speed }));
+        // Too close: fall back, far enough: close in
+        assert!(follow(idm.min_gap + speed * idm.time_headway) < 0.0);
+        assert!(follow(500.0) > 0.0);
+    }
+
+    #[test]
+    fn stopped_behind_a_stopped_leader_at_min_gap_stays_put() {
+        let (idm, d) = (Idm::default(), Dynamics::default());
+        let accel = idm.acceleration(&d, 0.0, f64::INFINITY, Some(Leader { gap: idm.min_gap, speed: 0.0 }));
+        assert!(accel.abs() < 1e-9);
+    }
+}
@@ -1,3 +1,5 @@
 mod car;
+mod dynamics;
 
-pub use car::*;
\ No newline at end of file
+pub use car::*;
+pub use dynamics::*;
\ No newline at end of file
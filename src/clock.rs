@@ -0,0 +1,73 @@
+/// Never run more than this many simulation steps for a single rendered frame,
+/// otherwise a slow frame makes the next one slower (spiral of death).
+const MAX_STEPS_PER_FRAME: u32 = 10;
+
+/// Fixed-timestep clock: turns wall-clock frame times into a whole number of
+/// simulation steps of `dt` seconds, carrying the remainder over to the next frame.
+pub struct SimClock {
+    dt: f64,
+    accumulator: f64,
+}
+
+impl SimClock {
+    pub fn new(dt: f64) -> Self {
+        Self {
+            dt,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Feed the wall time elapsed since the previous frame, in seconds.
+    /// Returns how many simulation steps have to be run now (possibly zero).
+    pub fn advance(&mut self, elapsed: f64) -> u32 {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.dt && steps < MAX_STEPS_PER_FRAME {
+            self.accumulator -= self.dt;
+            steps += 1;
+        }
+        if steps == MAX_STEPS_PER_FRAME {
+            self.accumulator = self.accumulator.min(self.dt);
+        }
+        steps
+    }
+
+    /// How far we are between the last simulated state and the next one, in `[0,1)`.
+    /// Used by the renderer to interpolate positions.
+    pub fn alpha(&self) -> f64 {
+        (self.accumulator / self.dt).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_the_remainder_over() {
+        let mut clock = SimClock::new(0.25);
+        assert_eq!(clock.advance(0.1), 0);
+        assert!((clock.alpha() - 0.4).abs() < 1e-9);
+        assert_eq!(clock.advance(0.2), 1);
+        assert!((clock.alpha() - 0.2).abs() < 1e-9);
+        assert_eq!(clock.advance(0.5), 2);
+        assert!((clock.alpha() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frame_times_add_up_to_whole_steps() {
+        let mut clock = SimClock::new(1.0 / 60.0);
+        let steps: u32 = (0..120).map(|_| clock.advance(1.0 / 120.0)).sum();
+        assert!((59..=60).contains(&steps));
+    }
+
+    #[test]
+    fn slow_frame_is_clamped() {
+        let mut clock = SimClock::new(0.1);
+        assert_eq!(clock.advance(5.0), MAX_STEPS_PER_FRAME);
+        // The backlog is dropped, at most one step is left for the next frame
+        assert!(clock.alpha() <= 1.0);
+        assert!(clock.advance(0.0) <= 1);
+        assert_eq!(clock.advance(0.0), 0);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+/// Seconds every car of a wait-for cycle must have been standing still for it to be a deadlock.
+pub const DEADLOCK_S: f64 = 2.0;
+
+/// What to do when cars wait on each other in a circle.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Recovery {
+    /// Give the car of the cycle held first of its lane at its stop line for the longest priority
+    /// over the others. Fails the run if none can have it, or if the cycle still stands
+    /// `DEADLOCK_S` after.
+    Priority,
+    /// Stop the run and report it as failed.
+    Abort,
+}
+
+impl std::str::FromStr for Recovery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "priority" => Ok(Recovery::Priority),
+            "abort" => Ok(Recovery::Abort),
+            _ => Err(format!("`{}` is not a deadlock recovery, expected priority or abort", s)),
+        }
+    }
+}
+
+/// Cars found waiting on each other in a circle.
+#[derive(Debug,Clone)]
+pub struct Deadlock {
+    /// Simulated time it was found at, in seconds.
+    pub time: f64,
+    /// Ids of the cars of the cycle, each waiting for the next and the last for the first.
+    pub cars: Vec<u32>,
+    /// Car given priority to break it, if one could be.
+    pub released: Option<u32>,
+}
+
+/// Who waits for whom: an edge from a car standing still to each car it is waiting for,
+/// the car it follows or the cars its intersection controller makes it give way to.
+#[derive(Debug,Clone,Default)]
+pub struct WaitForGraph {
+    edges: HashMap<u32, Vec<u32>>,
+}
+
+impl WaitForGraph {
+    /// Car `car` waits for car `other`.
+    pub fn add(&mut self, car: u32, other: u32) {
+        let targets = self.edges.entry(car).or_default();
+        if !targets.contains(&other) {
+            targets.push(other);
+        }
+    }
+
+    /// Car `car` no longer waits for car `other`.
+    pub fn remove(&mut self, car: u32, other: u32) {
+        if let Some(targets) = self.edges.get_mut(&car) {
+            targets.retain(|&o| o != other);
+        }
+    }
+
+    /// A cycle of the graph, in the order cars wait for each other, if there is one.
+    pub fn find_cycle(&self) -> Option<Vec<u32>> {
+        // Depth-first search keeping the current path, a car met again on it closes a cycle
+        let mut done: Vec<u32> = Vec::new();
+        let mut starts: Vec<u32> = self.edges.keys().copied().collect();
+        starts.sort_unstable();
+        for start in starts {
+            if done.contains(&start) {
+                continue;
+            }
+            let mut path = vec![start];
+            let mut next = vec![0];
+            while let Some(&car) = path.last() {
+                let depth = path.len() - 1;
+                let targets = self.edges.get(&car).map_or(&[][..], Vec::as_slice);
+                let Some(&other) = targets.get(next[depth]) else {
+                    done.push(car);
+                    path.pop();
+                    next.pop();
+                    continue;
+                };
+                next[depth] += 1;
+                if let Some(i) = path.iter().position(|&c| c == other) {
+                    return Some(path[i..].to_vec());
+                }
+                if !done.contains(&other) {
+                    path.push(other);
+                    next.push(0);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(u32, u32)]) -> WaitForGraph {
+        let mut graph = WaitForGraph::default();
+        for &(car, other) in edges {
+            graph.add(car, other);
+        }
+        graph
+    }
+
+    /// The cycle starting from its smallest id, to compare cycles found from any car.
+    fn normalized(mut cycle: Vec<u32>) -> Vec<u32> {
+        let first = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+        cycle.rotate_left(first);
+        cycle
+    }
+
+    #[test]
+    fn no_cycle() {
+        assert_eq!(graph(&[]).find_cycle(), None);
+        assert_eq!(graph(&[(1, 2), (2, 3), (1, 3), (4, 3)]).find_cycle(), None);
+    }
+
+    #[test]
+    fn simple_cycle() {
+        let cycle = graph(&[(1, 2), (2, 3), (3, 1)]).find_cycle().unwrap();
+        assert_eq!(normalized(cycle), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn cycle_reached_from_outside() {
+        let cycle = graph(&[(1, 2), (2, 3), (3, 4), (4, 2), (5, 1)]).find_cycle().unwrap();
+        assert_eq!(normalized(cycle), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn overlapping_cycles() {
+        // 7 and 8 are in both 7 -> 8 -> 7 and 7 -> 8 -> 9 -> 7
+        let graph = graph(&[(7, 8), (8, 9), (9, 7), (8, 7)]);
+        let cycle = graph.find_cycle().unwrap();
+        assert!([vec![7, 8], vec![7, 8, 9]].contains(&normalized(cycle.clone())));
+        for (i, &car) in cycle.iter().enumerate() {
+            assert!(graph.edges[&car].contains(&cycle[(i + 1) % cycle.len()]));
+        }
+    }
+
+    #[test]
+    fn removing_an_edge_breaks_its_cycle() {
+        let mut graph = graph(&[(1, 2), (2, 1), (3, 4), (4, 3)]);
+        let first = graph.find_cycle().unwrap();
+        graph.remove(first[0], first[1]);
+        let second = graph.find_cycle().unwrap();
+        assert!(second.iter().all(|car| !first.contains(car)));
+        graph.remove(second[0], second[1]);
+        assert_eq!(graph.find_cycle(), None);
+    }
+
+    #[test]
+    fn self_wait() {
+        assert_eq!(graph(&[(1, 2), (2, 2)]).find_cycle(), Some(vec![2]));
+    }
+
+    #[test]
+    fn duplicate_edges_are_kept_once() {
+        let graph = graph(&[(1, 2), (1, 2)]);
+        assert_eq!(graph.edges[&1], vec![2]);
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::map::{Direction, Network, Port};
+
+/// How long a flow with no demand waits before looking at its profile again, in seconds.
+const IDLE_RECHECK_S: f64 = 1.0;
+
+/// How the time between two cars of a flow is drawn.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arrivals {
+    /// Exponential gaps, cars arrive at random at the flow rate.
+    Poisson,
+    /// Evenly spaced cars.
+    Fixed,
+}
+
+/// Demand multiplier over the day, linear between its points and flat before the first and after the last.
+#[derive(Debug,Clone)]
+pub struct Profile {
+    /// Seconds since midnight and multiplier, sorted by time.
+    points: Vec<(f64, f64)>,
+}
+
+impl Profile {
+    pub fn factor(&self, time_of_day: f64) -> f64 {
+        let i = self.points.partition_point(|&(t, _)| t <= time_of_day);
+        match (self.points.get(i.wrapping_sub(1)), self.points.get(i)) {
+            (Some(&(t0, f0)), Some(&(t1, f1))) => f0 + (f1 - f0) * (time_of_day - t0) / (t1 - t0),
+            (Some(&(_, f)), None) | (None, Some(&(_, f))) => f,
+            (None, None) => 1.0,
+        }
+    }
+}
+
+/// Cars going from any of the `from` entries to any of the `to` exits.
+#[derive(Debug,Clone)]
+pub struct Flow {
+    pub from: Vec<Port>,
+    pub to: Vec<Port>,
+    /// Vehicles per hour before the profile is applied.
+    pub rate: f64,
+    profile: Option<usize>,
+
+    /// Simulated time of the next draw, and whether a car arrives then.
+    next: f64,
+    arriving: bool,
+}
+
+/// A car asked for by the demand, waiting to be spawned.
+#[derive(Debug,Clone,Copy)]
+pub struct Trip {
+    pub entry: Port,
+    pub exit: Port,
+}
+
+/// Origin-destination matrix of a network, turned into car arrivals over time.
+#[derive(Debug,Clone)]
+pub struct Demand {
+    pub name: String,
+    /// Time of day at the start of the simulation, in seconds since midnight.
+    pub start: f64,
+    pub arrivals: Arrivals,
+    pub flows: Vec<Flow>,
+    profiles: Vec<Profile>,
+}
+
+// What the file looks like, turned into a `Demand` by `Demand::parse`.
+
+#[derive(Deserialize)]
+struct FlowFile {
+    from: String,
+    to: String,
+    rate: f64,
+    profile: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DemandFile {
+    name: String,
+    start: String,
+    arrivals: Arrivals,
+    #[serde(default)]
+    profiles: HashMap<String, Vec<(String, f64)>>,
+    flows: Vec<FlowFile>,
+}
+
+/// Seconds since midnight of a `HH:MM` or `HH:MM:SS` time.
+fn time_of_day(text: &str) -> Result<f64, String> {
+    let parts: Vec<&str> = text.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return Err(format!("`{}` is not a HH:MM time", text));
+    }
+    parts.iter().zip([3600.0, 60.0, 1.0])
+        .map(|(p, unit)| p.parse::<f64>().map(|v| v * unit).map_err(|_| format!("`{}` is not a HH:MM time", text)))
+        .sum()
+}
+
+/// Ports of `ports` matching `endpoint`: a side of the network (`west`) or a side of one junction (`a1:west`).
+fn resolve(endpoint: &str, ports: &[Port], network: &Network) -> Result<Vec<Port>, String> {
+    let (node, side) = match endpoint.split_once(':') {
+        Some((node, side)) => {
+            let index = network.nodes.iter().position(|n| n.name == node)
+                .ok_or_else(|| format!("no junction named `{}`", node))?;
+            (Some(index), side)
+        },
+        None => (None, endpoint),
+    };
+    let side: Direction = side.parse()?;
+    let matching: Vec<Port> = ports.iter().copied()
+        .filter(|p| p.side == side && node.is_none_or(|n| p.node == n))
+        .collect();
+    if matching.is_empty() {
+        return Err(format!("no entry or exit of the network at `{}`", endpoint));
+    }
+    Ok(matching)
+}
+
+impl Demand {
+    pub fn load(path: &str, network: &Network) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text, network).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str, network: &Network) -> Result<Self, String> {
+        let file: DemandFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut names = Vec::new();
+        let mut profiles = Vec::new();
+        for (name, points) in file.profiles {
+            let mut points = points.into_iter()
+                .map(|(t, f)| time_of_day(&t).map(|t| (t, f)))
+                .collect::<Result<Vec<_>, _>>()?;
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            if points.iter().any(|&(_, f)| f < 0.0) {
+                return Err(format!("profile `{}` has a negative multiplier", name));
+            }
+            names.push(name);
+            profiles.push(Profile { points });
+        }
+
+        let mut flows = Vec::new();
+        for f in file.flows {
+            if f.rate < 0.0 {
+                return Err(format!("flow {} -> {} has a negative rate", f.from, f.to));
+            }
+            let from = resolve(&f.from, &network.entries, network)?;
+            let to = resolve(&f.to, &network.exits, network)?;
+            if !from.iter().any(|&e| to.iter().any(|&x| network.turns(e, x, &[]).is_some())) {
+                return Err(format!("`{}` cannot be reached from `{}`", f.to, f.from));
+            }
+            let profile = match f.profile {
+                Some(p) => Some(names.iter().position(|n| *n == p).ok_or_else(|| format!("no profile named `{}`", p))?),
+                None => None,
+            };
+            flows.push(Flow { from, to, rate: f.rate, profile, next: 0.0, arriving: false });
+        }
+
+        Ok(Self {
+            name: file.name,
+            start: time_of_day(&file.start)?,
+            arrivals: file.arrivals,
+            flows,
+            profiles,
+        })
+    }
+
+    /// Vehicles per hour asked for by `flow` at `time` seconds into the simulation.
+    pub fn rate(&self, flow: &Flow, time: f64) -> f64 {
+        let factor = flow.profile.map_or(1.0, |p| self.profiles[p].factor(self.start + time));
+        flow.rate * factor
+    }
+
+    /// Trips starting up to `time` seconds into the simulation and not returned yet.
+    pub fn arrivals<R: Rng>(&mut self, time: f64, network: &Network, rng: &mut R) -> Vec<Trip> {
+        let mut trips = Vec::new();
+        for i in 0..self.flows.len() {
+            while self.flows[i].next <= time {
+                let flow = &self.flows[i];
+                if flow.arriving {
+                    let entry = flow.from[rng.gen_range(0..flow.from.len())];
+                    let exits: Vec<Port> = flow.to.iter().copied()
+                        .filter(|&x| network.turns(entry, x, &[]).is_some())
+                        .collect();
+                    if !exits.is_empty() {
+                        trips.push(Trip { entry, exit: exits[rng.gen_range(0..exits.len())] });
+                    }
+                }
+
+                let per_second = self.rate(flow, flow.next) / 3600.0;
+                let gap = match self.arrivals {
+                    _ if per_second <= 0.0 => None,
+                    Arrivals::Fixed => Some(1.0 / per_second),
+                    Arrivals::Poisson => Some(-rng.gen_range(f64::EPSILON..=1.0).ln() / per_second),
+                };
+                let flow = &mut self.flows[i];
+                flow.next += gap.unwrap_or(IDLE_RECHECK_S);
+                flow.arriving = gap.is_some();
+            }
+        }
+        trips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::map::Layout;
+
+    fn junction() -> Network {
+        Network::single(Layout::load("assets/intersection.toml").unwrap())
+    }
+
+    fn parse(flows: &str) -> Result<Demand, String> {
+        let text = format!(r#"
+            name = "Test"
+            start = "07:30"
+            arrivals = "fixed"
+            [profiles]
+            peak = [["08:00", 2.0], ["07:00", 1.0]]
+            {}
+        "#, flows);
+        Demand::parse(&text, &junction())
+    }
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(time_of_day("07:30"), Ok(27000.0));
+        assert_eq!(time_of_day("00:00:15"), Ok(15.0));
+        assert!(time_of_day("7").is_err());
+        assert!(time_of_day("07:xx").is_err());
+    }
+
+    #[test]
+    fn profile_interpolates_and_holds_its_ends() {
+        let profile = Profile { points: vec![(100.0, 1.0), (200.0, 3.0), (300.0, 0.0)] };
+        assert_eq!(profile.factor(0.0), 1.0);
+        assert_eq!(profile.factor(100.0), 1.0);
+        assert_eq!(profile.factor(150.0), 2.0);
+        assert_eq!(profile.factor(250.0), 1.5);
+        assert_eq!(profile.factor(1000.0), 0.0);
+        assert_eq!(Profile { points: Vec::new() }.factor(50.0), 1.0);
+    }
+
+    #[test]
+    fn rate_follows_the_profile_from_the_start_time() {
+        let demand = parse(r#"
+            [[flows]]
+            from = "north"
+            to = "south"
+            rate = 100
+            profile = "peak"
+        "#).unwrap();
+        let flow = &demand.flows[0];
+        // Profile points are sorted, 07:30 is half way up
+        assert!((demand.rate(flow, 0.0) - 150.0).abs() < 1e-9);
+        assert!((demand.rate(flow, 1800.0) - 200.0).abs() < 1e-9);
+        assert!((demand.rate(flow, 7200.0) - 200.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fixed_arrivals_match_the_rate() {
+        let mut demand = parse(r#"
+            [[flows]]
+            from = "west"
+            to = "east"
+            rate = 360
+        "#).unwrap();
+        let network = junction();
+        let mut rng = StdRng::seed_from_u64(1);
+        let trips = demand.arrivals(100.0, &network, &mut rng);
+        assert_eq!(trips.len(), 10);
+        assert!(trips.iter().all(|t| t.entry.side == Direction::West && t.exit.side == Direction::East));
+        assert!(demand.arrivals(100.0, &network, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn bad_flows_are_rejected() {
+        let flow = |from: &str, to: &str, rate: &str, profile: &str| parse(&format!(
+            "[[flows]]\nfrom = \"{}\"\nto = \"{}\"\nrate = {}\n{}", from, to, rate, profile));
+        assert!(flow("north", "south", "60", "").is_ok());
+        assert!(flow("north", "south", "-1", "").is_err());
+        assert!(flow("north", "south", "60", "profile = \"evening\"").is_err());
+        assert!(flow("north", "north", "60", "").is_err());
+        assert!(flow("up", "south", "60", "").is_err());
+        assert!(flow("a1:north", "south", "60", "").is_err());
+    }
+}
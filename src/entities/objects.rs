@@ -1,6 +1,8 @@
-use sdl2::{rect::Rect, render::Canvas, video::Window};
+use sdl2::{render::{Canvas, Texture}, video::Window};
+
+use smart_road_remake::geometry::Rect;
 
 pub trait Entity {
-    fn display(&self, canvas: &mut Canvas<Window>) -> Result<(), Box<dyn std::error::Error>>;
+    fn display(&self, canvas: &mut Canvas<Window>, atlas: &Texture, alpha: f64, debug: bool) -> Result<(), Box<dyn std::error::Error>>;
     fn get_hitbox(&self) -> Rect;
-}
\ No newline at end of file
+}
@@ -2,13 +2,15 @@ use sdl2::EventPump;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
-use crate::map::Direction;
+use smart_road_remake::map::Direction;
 
-#[allow(dead_code)]
+/// Destinations are not picked here, the simulation draws them from its own seeded RNG.
 pub enum Type {
     Quit,
-    SpawnCar(Direction,Direction),
+    SpawnCar(Direction),
+    SpawnRandomCar,
     ToggleDebug,
+    NextMode,
     None,
 }
 
@@ -21,16 +23,14 @@ pub fn handle(ep: &mut EventPump) -> Type {
                 ..
             } => return Type::Quit,
             Event::KeyDown { keycode: Some(Keycode::D), .. } => return Type::ToggleDebug,
-            Event::KeyDown { keycode: Some(Keycode::UP), .. } => return Type::SpawnCar(Direction::South,Direction::random_without(Direction::South)),
-            Event::KeyDown { keycode: Some(Keycode::DOWN), .. } => return Type::SpawnCar(Direction::North,Direction::random_without(Direction::North)),
-            Event::KeyDown { keycode: Some(Keycode::RIGHT), .. } => return Type::SpawnCar(Direction::West,Direction::random_without(Direction::West)),
-            Event::KeyDown { keycode: Some(Keycode::LEFT), .. } => return Type::SpawnCar(Direction::East,Direction::random_without(Direction::East)),
-            Event::KeyDown { keycode: Some(Keycode::R), .. } => {
-                let spawning_direction = Direction::random();
-                return Type::SpawnCar(spawning_direction,Direction::random_without(spawning_direction));
-            },
-            _ => return Type::None,
+            Event::KeyDown { keycode: Some(Keycode::T), .. } => return Type::NextMode,
+            Event::KeyDown { keycode: Some(Keycode::UP), .. } => return Type::SpawnCar(Direction::South),
+            Event::KeyDown { keycode: Some(Keycode::DOWN), .. } => return Type::SpawnCar(Direction::North),
+            Event::KeyDown { keycode: Some(Keycode::RIGHT), .. } => return Type::SpawnCar(Direction::West),
+            Event::KeyDown { keycode: Some(Keycode::LEFT), .. } => return Type::SpawnCar(Direction::East),
+            Event::KeyDown { keycode: Some(Keycode::R), .. } => return Type::SpawnRandomCar,
+            _ => {},
         }
     };
     Type::None
-}
\ No newline at end of file
+}
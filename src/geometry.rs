@@ -0,0 +1,392 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+/// Sub-pixel position or direction in screen coordinates (y grows downwards).
+#[derive(Debug,Default,Clone,Copy,PartialEq)]
+pub struct Vec2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Vec2 {
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    /// Unit vector pointing at `heading` radians (0 = +x, turning towards +y).
+    pub fn from_angle(heading: f64) -> Self {
+        Self::new(heading.cos(), heading.sin())
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(self) -> f64 {
+        self.x.hypot(self.y)
+    }
+
+    /// Unit vector in the same direction, or zero for the zero vector.
+    pub fn normalized(self) -> Self {
+        let l = self.length();
+        if l == 0.0 {
+            return Self::default();
+        }
+        self * (1.0 / l)
+    }
+
+    /// The same vector turned by +90°.
+    pub fn perpendicular(self) -> Self {
+        Self::new(-self.y, self.x)
+    }
+
+    pub fn angle(self) -> f64 {
+        self.y.atan2(self.x)
+    }
+
+    pub fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+
+    /// Nearest pixel.
+    pub fn to_point(self) -> Point {
+        Point::new(self.x.round() as i32, self.y.round() as i32)
+    }
+}
+
+/// Pixel position in screen coordinates (y grows downwards).
+#[derive(Debug,Default,Clone,Copy,PartialEq,Eq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+/// Axis aligned rectangle of whole pixels, `x` and `y` being its top left corner.
+#[derive(Debug,Default,Clone,Copy,PartialEq,Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    /// Rectangle of `w` by `h` pixels centred on `center`.
+    pub fn from_center(center: Point, w: u32, h: u32) -> Self {
+        Self::new(center.x - (w / 2) as i32, center.y - (h / 2) as i32, w, h)
+    }
+
+    pub fn center(&self) -> Point {
+        Point::new(self.x + (self.w / 2) as i32, self.y + (self.h / 2) as i32)
+    }
+
+    /// Whether the two rectangles share some area.
+    pub fn has_intersection(&self, other: Rect) -> bool {
+        let (right, bottom) = (self.x + self.w as i32, self.y + self.h as i32);
+        let (other_right, other_bottom) = (other.x + other.w as i32, other.y + other.h as i32);
+        self.w > 0 && self.h > 0 && other.w > 0 && other.h > 0
+            && self.x < other_right && other.x < right && self.y < other_bottom && other.y < bottom
+    }
+}
+
+/// Points sampled along the cubic Bézier curve `p0 p1 p2 p3`, both ends included.
+pub fn cubic_bezier(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, segments: usize) -> Vec<Vec2> {
+    (0..=segments).map(|i| {
+        let t = i as f64 / segments as f64;
+        let u = 1.0 - t;
+        p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+    }).collect()
+}
+
+/// Polyline parameterised by arc length, what cars drive along.
+#[derive(Debug,Clone,Default)]
+pub struct Path {
+    points: Vec<Vec2>,
+    /// `cumulative[i]` is the arc length from the start to `points[i]`.
+    cumulative: Vec<f64>,
+}
+
+impl Path {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        let mut points: Vec<Vec2> = points;
+        points.dedup_by(|a, b| (*a - *b).length() < 1e-9);
+        let mut cumulative = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (i, p) in points.iter().enumerate() {
+            if i > 0 {
+                total += (*p - points[i - 1]).length();
+            }
+            cumulative.push(total);
+        }
+        Self { points, cumulative }
+    }
+
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    /// This path with its part between arc lengths `from` and `to` replaced by `points`.
+    pub fn splice(&self, from: f64, to: f64, points: Vec<Vec2>) -> Self {
+        let before = self.points.iter().zip(&self.cumulative).filter(|&(_, &s)| s < from).map(|(&p, _)| p);
+        let after = self.points.iter().zip(&self.cumulative).filter(|&(_, &s)| s > to).map(|(&p, _)| p);
+        Self::new(before.chain(points).chain(after).collect())
+    }
+
+    pub fn length(&self) -> f64 {
+        self.cumulative.last().copied().unwrap_or(0.0)
+    }
+
+    /// Index of the segment containing arc length `s`, clamped to the path.
+    fn segment_at(&self, s: f64) -> usize {
+        let i = self.cumulative.partition_point(|&c| c <= s);
+        i.clamp(1, self.points.len().max(2) - 1) - 1
+    }
+
+    pub fn point_at(&self, s: f64) -> Vec2 {
+        match self.points.len() {
+            0 => Vec2::default(),
+            1 => self.points[0],
+            _ => {
+                let i = self.segment_at(s);
+                let length = self.cumulative[i + 1] - self.cumulative[i];
+                let t = ((s - self.cumulative[i]) / length).clamp(0.0, 1.0);
+                self.points[i].lerp(self.points[i + 1], t)
+            },
+        }
+    }
+
+    /// Direction of travel at arc length `s`, in radians.
+    pub fn heading_at(&self, s: f64) -> f64 {
+        if self.points.len() < 2 {
+            return 0.0;
+        }
+        let i = self.segment_at(s);
+        (self.points[i + 1] - self.points[i]).angle()
+    }
+
+    /// Arc length and lateral distance of the point of the path closest to `p`,
+    /// looking only between arc lengths `from` and `to`. Segments whose direction
+    /// differs from `heading` by more than 60° are ignored.
+    pub fn project(&self, p: Vec2, heading: f64, from: f64, to: f64) -> Option<(f64, f64)> {
+        let along_heading = Vec2::from_angle(heading);
+        let mut best: Option<(f64, f64)> = None;
+        for i in 0..self.points.len().saturating_sub(1) {
+            let (s0, s1) = (self.cumulative[i], self.cumulative[i + 1]);
+            if s1 < from || s0 > to {
+                continue;
+            }
+            let (a, b) = (self.points[i], self.points[i + 1]);
+            let dir = (b - a).normalized();
+            if dir.dot(along_heading) < 0.5 {
+                continue;
+            }
+            let t = (p - a).dot(dir).clamp(0.0, s1 - s0);
+            let s = s0 + t;
+            if s < from || s > to {
+                continue;
+            }
+            let lateral = (p - (a + dir * t)).length();
+            if best.is_none_or(|(_, l)| lateral < l) {
+                best = Some((s, lateral));
+            }
+        }
+        best
+    }
+}
+
+/// Oriented bounding box: a rectangle of `length` along `heading` and `width` across it.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Obb {
+    pub center: Vec2,
+    pub heading: f64,
+    pub length: f64,
+    pub width: f64,
+}
+
+impl Obb {
+    pub fn new(center: Vec2, heading: f64, length: f64, width: f64) -> Self {
+        Self { center, heading, length, width }
+    }
+
+    /// Unit vectors along the length and across the width.
+    fn axes(&self) -> [Vec2; 2] {
+        let forward = Vec2::from_angle(self.heading);
+        [forward, forward.perpendicular()]
+    }
+
+    /// Corners in drawing order: front left, front right, rear right, rear left.
+    pub fn corners(&self) -> [Vec2; 4] {
+        let [f, p] = self.axes();
+        let f = f * (self.length / 2.0);
+        let p = p * (self.width / 2.0);
+        let c = self.center;
+        [c + f - p, c + f + p, c - f + p, c - f - p]
+    }
+
+    /// Smallest axis aligned rectangle containing the box, for broad phase checks and drawing.
+    pub fn aabb(&self) -> Rect {
+        let corners = self.corners();
+        let (mut min, mut max) = (corners[0], corners[0]);
+        for c in &corners[1..] {
+            min = Vec2::new(min.x.min(c.x), min.y.min(c.y));
+            max = Vec2::new(max.x.max(c.x), max.y.max(c.y));
+        }
+        let size = max - min;
+        Rect::from_center((min.lerp(max, 0.5)).to_point(), size.x.round().max(1.0) as u32, size.y.round().max(1.0) as u32)
+    }
+
+    /// Half of the extent of the box projected on `axis`.
+    pub fn projected_radius(&self, axis: Vec2) -> f64 {
+        let [f, p] = self.axes();
+        (f.dot(axis) * self.length / 2.0).abs() + (p.dot(axis) * self.width / 2.0).abs()
+    }
+
+    /// Separating axis test: two boxes overlap unless one of their four edge normals separates them.
+    pub fn intersects(&self, other: &Obb) -> bool {
+        let between = other.center - self.center;
+        self.axes().into_iter().chain(other.axes()).all(|axis| {
+            between.dot(axis).abs() < self.projected_radius(axis) + other.projected_radius(axis)
+        })
+    }
+}
+
+impl From<Point> for Vec2 {
+    fn from(p: Point) -> Self {
+        Self::new(p.x as f64, p.y as f64)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        Self::new(self.x + o.x, self.y + o.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, o: Self) {
+        *self = *self + o;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        Self::new(self.x - o.x, self.y - o.y)
+    }
+}
+
+impl Mul<f64> for Vec2 {
+    type Output = Self;
+    fn mul(self, k: f64) -> Self {
+        Self::new(self.x * k, self.y * k)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_4;
+
+    use super::*;
+
+    fn square(x: f64, y: f64, heading: f64) -> Obb {
+        Obb::new(Vec2::new(x, y), heading, 10.0, 10.0)
+    }
+
+    #[test]
+    fn overlapping_boxes_intersect() {
+        assert!(square(0.0, 0.0, 0.0).intersects(&square(8.0, 3.0, 0.0)));
+        assert!(square(0.0, 0.0, 0.0).intersects(&square(0.0, 0.0, FRAC_PI_4)));
+    }
+
+    #[test]
+    fn box_inside_another_intersects() {
+        let outer = Obb::new(Vec2::new(0.0, 0.0), 0.3, 100.0, 50.0);
+        assert!(outer.intersects(&square(5.0, 5.0, 1.0)));
+        assert!(square(5.0, 5.0, 1.0).intersects(&outer));
+    }
+
+    #[test]
+    fn separated_boxes_do_not_intersect() {
+        assert!(!square(0.0, 0.0, 0.0).intersects(&square(20.0, 0.0, 0.0)));
+        assert!(!square(0.0, 0.0, 0.0).intersects(&square(0.0, -11.0, 0.0)));
+    }
+
+    #[test]
+    fn touching_edges_do_not_intersect() {
+        assert!(!square(0.0, 0.0, 0.0).intersects(&square(10.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn rotated_boxes_separated_along_their_own_axes() {
+        // The bounding boxes overlap, but a diamond next to the corner of a square does not touch it
+        let a = square(0.0, 0.0, 0.0);
+        let b = square(10.0, 10.0, FRAC_PI_4);
+        assert!(a.aabb().has_intersection(b.aabb()));
+        assert!(!a.intersects(&b));
+        assert!(!b.intersects(&a));
+    }
+
+    #[test]
+    fn long_thin_boxes_crossing_intersect() {
+        let a = Obb::new(Vec2::new(0.0, 0.0), 0.0, 100.0, 4.0);
+        let b = Obb::new(Vec2::new(0.0, 0.0), std::f64::consts::FRAC_PI_2, 100.0, 4.0);
+        assert!(a.intersects(&b));
+        assert!(!a.intersects(&Obb::new(Vec2::new(0.0, 10.0), 0.0, 100.0, 4.0)));
+    }
+
+    #[test]
+    fn splice_replaces_the_middle_of_a_path() {
+        let path = Path::new(vec![Vec2::new(0.0, 0.0), Vec2::new(50.0, 0.0), Vec2::new(100.0, 0.0)]);
+        let detour = path.splice(20.0, 80.0, vec![Vec2::new(20.0, 0.0), Vec2::new(50.0, 40.0), Vec2::new(80.0, 0.0)]);
+        assert_eq!(detour.points().len(), 5);
+        assert!((detour.length() - 140.0).abs() < 1e-9);
+        assert_eq!(detour.point_at(70.0), Vec2::new(50.0, 40.0));
+        assert_eq!(detour.point_at(140.0), Vec2::new(100.0, 0.0));
+    }
+
+    #[test]
+    fn straight_bezier_is_as_long_as_its_chord() {
+        let path = Path::new(cubic_bezier(Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(90.0, 0.0), Vec2::new(100.0, 0.0), 16));
+        assert!((path.length() - 100.0).abs() < 1e-9);
+        assert_eq!(path.point_at(path.length()), Vec2::new(100.0, 0.0));
+    }
+
+    #[test]
+    fn quarter_turn_bezier_has_the_arc_length_of_a_quarter_circle() {
+        // Control points at k * r from the ends approximate a circle of radius r
+        let (r, k) = (100.0, 0.5523);
+        let path = Path::new(cubic_bezier(
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, k * r),
+            Vec2::new(r - k * r, r),
+            Vec2::new(r, r),
+            32,
+        ));
+        let quarter = std::f64::consts::FRAC_PI_2 * r;
+        assert!((path.length() - quarter).abs() < 0.1);
+        // Tangent to the straight lanes at both ends
+        assert!((path.heading_at(0.0) - std::f64::consts::FRAC_PI_2).abs() < 0.05);
+        assert!(path.heading_at(path.length()).abs() < 0.05);
+        // Half way along the arc is on the circle, at 45°
+        let middle = path.point_at(quarter / 2.0);
+        assert!(((middle - Vec2::new(r, 0.0)).length() - r).abs() < 0.5);
+        assert!((middle.x - (r - r / 2f64.sqrt())).abs() < 0.5);
+    }
+}
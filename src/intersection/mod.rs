@@ -0,0 +1,242 @@
+mod priority;
+mod stop;
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use priority::*;
+pub use stop::*;
+
+use crate::cars::{Car, TURN_VELOCITY};
+use crate::map::{ConflictGrid, Crossing, Direction, Lane, Network, TrafficLight};
+use crate::occupancy::Occupancy;
+use crate::reservation::ReservationManager;
+use crate::signals::{Detectors, SignalController, SignalPlan};
+
+/// Distance to its stop line at which a car is announced to the junction, in pixels.
+pub const APPROACH_DISTANCE: f64 = 300.0;
+/// Time to the conflict a yielding car needs to go, in seconds.
+pub const ACCEPTED_GAP_S: f64 = 2.0;
+/// How far past the end of a junction a car looks for a queue it would have to join, in pixels.
+const EXIT_LOOKAHEAD: f64 = 300.0;
+
+/// How every junction of the network is run.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Mode {
+    /// Signal heads driven by the phases of a signal plan.
+    Signals,
+    /// Cars book the cells of the conflict zone they drive over.
+    Reservation,
+    /// Every car stops at the line, then goes in turn.
+    AllWayStop,
+    /// No control, cars give way to the ones coming from their right.
+    PriorityToRight,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::Signals, Mode::Reservation, Mode::AllWayStop, Mode::PriorityToRight];
+
+    /// The mode after this one in `Mode::ALL`, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// Controller running junction `node` of `network` in this mode, with `plan` for signals.
+    pub fn controller(self, node: usize, network: &Network, grid: &ConflictGrid, plan: &SignalPlan) -> Box<dyn IntersectionController> {
+        match self {
+            Mode::Signals => Box::new(SignalController::new(node, network, plan)),
+            Mode::Reservation => Box::new(ReservationManager::new(grid)),
+            Mode::AllWayStop => Box::new(AllWayStop::default()),
+            Mode::PriorityToRight => Box::new(PriorityToRight::default()),
+        }
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "signals" => Ok(Mode::Signals),
+            "reservation" => Ok(Mode::Reservation),
+            "all-way-stop" => Ok(Mode::AllWayStop),
+            "priority-to-right" => Ok(Mode::PriorityToRight),
+            _ => Err(format!("`{}` is not an intersection mode, expected signals, reservation, all-way-stop or priority-to-right", s)),
+        }
+    }
+}
+
+/// What a controller may read of the simulation during a callback.
+pub struct Context<'a> {
+    /// Simulated time, in seconds.
+    pub time: f64,
+    pub cars: &'a [Car],
+    pub network: &'a Network,
+    /// Conflict grid of the junction the controller runs.
+    pub grid: &'a ConflictGrid,
+    pub detectors: &'a Detectors,
+    pub occupancy: &'a Occupancy,
+}
+
+impl Context<'_> {
+    /// Side of its junction `crossing` comes from.
+    pub fn side(&self, crossing: &Crossing) -> Direction {
+        let node = &self.network.nodes[crossing.node];
+        node.layout.approaches[crossing.light - node.first_light].from
+    }
+
+    /// Route of the conflict grid that `crossing` drives through the junction on.
+    pub fn route(&self, crossing: &Crossing) -> Option<usize> {
+        let first = self.network.nodes[crossing.node].first_light;
+        self.grid.route(crossing.light - first, crossing.lane, crossing.to)
+    }
+
+    /// Whether cars driving `a` and `b` through the junction sweep a common cell.
+    pub fn conflict(&self, a: &Crossing, b: &Crossing) -> bool {
+        match (self.route(a), self.route(b)) {
+            (Some(a), Some(b)) => self.grid.conflict(a, b),
+            _ => self.grid.movements_conflict((self.side(a), a.movement), (self.side(b), b.movement)),
+        }
+    }
+
+    /// Seconds `other`, driving through at `b`, needs to reach the first cell of its route that
+    /// `a`'s sweeps too, counting at least `TURN_VELOCITY`: zero while it is on one, `None` once
+    /// it is past them or if there is none.
+    pub fn time_to_conflict(&self, a: &Crossing, other: &Car, b: &Crossing) -> Option<f64> {
+        let x = past_line(other, b);
+        let distance = match (self.route(a), self.route(b)) {
+            (Some(ra), Some(rb)) => {
+                let routes = self.grid.routes();
+                routes[rb].cells.iter()
+                    .filter(|span| span.leave > x && routes[ra].cells.iter().any(|s| s.cell == span.cell))
+                    .map(|span| (span.enter - x).max(0.0))
+                    .min_by(f64::total_cmp)?
+            },
+            // Without routes in the grid, the whole junction is the conflict
+            _ => (-x).max(0.0),
+        };
+        self.conflict(a, b).then_some(distance / other.speed().max(TURN_VELOCITY))
+    }
+
+    /// Cars of the junction of `crossing` other than `car`, with the crossing they are in or
+    /// heading to.
+    pub fn others<'b>(&'b self, car: &'b Car, crossing: &'b Crossing) -> impl Iterator<Item = (&'b Car, Crossing)> + 'b {
+        self.cars.iter()
+            .filter(move |o| o.id() != car.id())
+            .filter_map(move |o| o.crossing().filter(|c| c.node == crossing.node).map(|c| (o, c)))
+    }
+}
+
+/// Distance from the front of `car` to its stop line at `crossing`, in pixels.
+pub fn distance_to_line(car: &Car, crossing: &Crossing) -> f64 {
+    crossing.stop - car.travelled() - car.w_l().1 as f64 / 2.0
+}
+
+/// Distance the front of `car` is past its stop line at `crossing`, the way the cells of a
+/// route in the conflict grid are spanned, in pixels.
+pub fn past_line(car: &Car, crossing: &Crossing) -> f64 {
+    -distance_to_line(car, crossing)
+}
+
+/// Seconds `car` needs to reach its stop line at `crossing`, counting at least `TURN_VELOCITY`.
+pub fn time_to_line(car: &Car, crossing: &Crossing) -> f64 {
+    distance_to_line(car, crossing) / car.speed().max(TURN_VELOCITY)
+}
+
+/// Whether `car` can still stop before its stop line at `crossing`, braking comfortably.
+pub fn can_stop(car: &Car, crossing: &Crossing) -> bool {
+    distance_to_line(car, crossing) >= car.dynamics().stopping_distance(car.speed())
+}
+
+/// Whether `car` is standing at its stop line at `crossing`, first of its lane.
+pub fn stopped_at_line(car: &Car, crossing: &Crossing) -> bool {
+    car.is_stopped() && distance_to_line(car, crossing) <= 2.0 * car.idm().min_gap
+}
+
+/// Car on the path of `car` past its stop line at `crossing` that leaves it no room to get all
+/// the way out of the junction. Up to the next stop line on its path, every car on the way or on
+/// the lanes of a lane change there, and every car from another approach on its way out by the same lane,
+/// counts as queued at that line; on a road leaving the network, the cars behind a standing one.
+pub fn exit_blocked(car: &Car, crossing: &Crossing, cars: &[Car]) -> Option<u32> {
+    let next_stop = car.crossings().iter().map(|c| c.stop).find(|&stop| stop > crossing.exit);
+    let end = next_stop.unwrap_or(crossing.exit + EXIT_LOOKAHEAD);
+    // A lane change before the next stop line may be put off until its latest start: the lane
+    // left up to the end of the move then, and the whole lane joined, count as on the way
+    let lanes: Vec<(Lane, f64)> = car.lane_changes().iter()
+        .filter(|c| c.origin > crossing.stop && c.origin < end)
+        .flat_map(|c| [(c.from, c.latest + c.length - c.origin), (c.to, (c.to.end - c.to.start).length())])
+        .collect();
+    let on_lanes = |o: &Car| lanes.iter().any(|(lane, until)| {
+        let p = o.obb().center - lane.start;
+        p.dot(lane.direction().perpendicular()).abs() < o.w_l().0 as f64 / 2.0 && (0.0..=*until).contains(&p.dot(lane.direction()))
+    });
+    let mut ahead: Vec<(f64, &Car)> = cars.iter()
+        .filter(|o| o.id() != car.id())
+        .filter_map(|o| Some((car.position_on_path(o, crossing.stop, end).or_else(|| on_lanes(o).then_some(crossing.exit))?, o)))
+        .collect();
+    // Cars from other approaches already on their way out of the junction by the same lane
+    let exit = car.point_at(crossing.exit);
+    let merging: Vec<_> = cars.iter().filter(|o| o.id() != car.id() && ahead.iter().all(|(_, a)| a.id() != o.id()))
+        .filter(|o| o.crossing().is_some_and(|c| {
+            c.node == crossing.node && c.light != crossing.light
+                && (o.has_entered_intersection() || !o.is_held() && !can_stop(o, &c))
+                && (o.point_at(c.exit) - exit).length() < o.w_l().0 as f64 / 2.0
+        }))
+        .map(|o| (crossing.exit, o))
+        .collect();
+    ahead.extend(merging);
+    ahead.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let space = |o: &Car| o.w_l().1 as f64 + o.idm().min_gap;
+    let needed = space(car);
+    match next_stop {
+        Some(stop) => {
+            let queued: f64 = ahead.iter().map(|(_, o)| space(o)).sum();
+            ahead.first().filter(|_| stop - crossing.exit - queued < needed).map(|(_, o)| o.id())
+        },
+        None => {
+            let standing = ahead.iter().position(|(_, o)| o.is_stopped())?;
+            let (at, blocker) = ahead[standing];
+            let queued: f64 = ahead[..standing].iter().map(|(_, o)| space(o)).sum();
+            (at - blocker.w_l().1 as f64 / 2.0 - queued < crossing.exit + needed).then_some(blocker.id())
+        },
+    }
+}
+
+/// Decides who may drive into one junction. The simulation announces every car coming within
+/// `APPROACH_DISTANCE` of its stop line, asks every step whether each car not in yet may cross
+/// its line, and tells when a car has left the junction. A car that would have to stop in the
+/// junction for want of room past it is held at its line without asking.
+pub trait IntersectionController {
+    /// `car` is coming up to its stop line at `crossing`.
+    fn on_approach(&mut self, _car: &Car, _crossing: &Crossing, _ctx: &Context) {}
+
+    /// Whether `car` may drive past its stop line at `crossing` now. A car told no stops at it.
+    fn may_enter(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool;
+
+    /// Cars that `car`, held at its stop line at `crossing`, is waiting for, for deadlock detection.
+    fn waiting_for(&self, _car: &Car, _crossing: &Crossing, _ctx: &Context) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// Let `car`, held first of its lane at its stop line at `crossing` in a deadlock, go before
+    /// the cars it waits for, the others giving way to it. Returns whether the controller can.
+    fn give_priority(&mut self, _car: &Car, _crossing: &Crossing, _ctx: &Context) -> bool {
+        false
+    }
+
+    /// Advance by `dt` seconds, once the cars have moved.
+    fn tick(&mut self, _dt: f64, _ctx: &Context) {}
+
+    /// Car `car` has left the junction, or the network.
+    fn on_exit(&mut self, _car: u32, _ctx: &Context) {}
+
+    /// Signal heads of the junction, if it has any.
+    fn lights(&self) -> &[TrafficLight] {
+        &[]
+    }
+
+    /// Cells of the conflict grid held for a car at `time`.
+    fn held_cells(&self, _time: f64) -> Vec<usize> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+
+use crate::cars::Car;
+use crate::map::{Crossing, Movement};
+use super::{distance_to_line, stopped_at_line, time_to_line, Context, IntersectionController, ACCEPTED_GAP_S};
+
+/// Uncontrolled junction: cars give way to the cars coming from their right, left turns to
+/// oncoming traffic as well, and nobody drives in while a car it conflicts with is inside.
+/// Of two opposing left turns, the one further from its line gives way.
+#[derive(Debug,Clone,Default)]
+pub struct PriorityToRight {
+    /// Cars let go that could no longer stop comfortably before their line.
+    committed: HashSet<u32>,
+    /// Cars given way to by all the others to break a deadlock, the last given it first.
+    priority: Vec<u32>,
+}
+
+impl PriorityToRight {
+    /// Cars `car` has to give way to before driving past its line at `crossing`: the cars in
+    /// the junction in its way, and the first car of each lane it gives way to that is moving
+    /// and less than `ACCEPTED_GAP_S` from its line.
+    fn yields_to(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> Vec<u32> {
+        let from = ctx.side(crossing);
+        let own = (time_to_line(car, crossing), car.id());
+        let rank = |id: u32| self.priority.iter().position(|&p| p == id);
+        let lead = |o: &Car, c: &Crossing| ctx.others(car, crossing)
+            .filter(|(p, pc)| p.id() != o.id() && !p.has_entered_intersection() && pc.light == c.light && pc.lane == c.lane)
+            .all(|(p, pc)| distance_to_line(p, &pc) > distance_to_line(o, c));
+        ctx.others(car, crossing)
+            .filter(|(o, c)| {
+                if !ctx.conflict(crossing, c) {
+                    return false;
+                }
+                if o.has_entered_intersection() {
+                    return true;
+                }
+                let side = ctx.side(c);
+                if side == from {
+                    return false;
+                }
+                match (rank(car.id()), rank(o.id())) {
+                    (Some(a), Some(b)) => return b < a,
+                    (Some(_), None) => return false,
+                    (None, Some(_)) => return true,
+                    (None, None) => {},
+                }
+                let theirs = (time_to_line(o, c), o.id());
+                if o.is_stopped() || theirs.0 >= ACCEPTED_GAP_S || !lead(o, c) {
+                    return false;
+                }
+                side == Movement::Right.exit(from)
+                    || (crossing.movement == Movement::Left && side == from.opposite()
+                        && (c.movement != Movement::Left || theirs < own))
+            })
+            .map(|(o, _)| o.id())
+            .collect()
+    }
+}
+
+impl IntersectionController for PriorityToRight {
+    fn may_enter(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        if self.committed.contains(&car.id()) {
+            return true;
+        }
+        let clear = self.yields_to(car, crossing, ctx).is_empty();
+        if clear && distance_to_line(car, crossing) <= car.dynamics().stopping_distance(car.speed()) {
+            self.committed.insert(car.id());
+        }
+        clear
+    }
+
+    fn waiting_for(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> Vec<u32> {
+        self.yields_to(car, crossing, ctx)
+    }
+
+    fn give_priority(&mut self, car: &Car, crossing: &Crossing, _ctx: &Context) -> bool {
+        if !stopped_at_line(car, crossing) {
+            return false;
+        }
+        self.priority.retain(|&id| id != car.id());
+        self.priority.insert(0, car.id());
+        true
+    }
+
+    fn on_exit(&mut self, car: u32, _ctx: &Context) {
+        self.committed.remove(&car);
+        self.priority.retain(|&id| id != car);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::Direction;
+    use super::super::testing::{drive, Junction};
+    use super::*;
+
+    /// Car `id` from `from` to the opposite side, driven alone for `seconds`.
+    fn approaching(junction: &Junction, id: u32, from: Direction, seconds: f64) -> Car {
+        let mut cars = [junction.car(id, from, from.opposite())];
+        drive(&mut cars, seconds);
+        cars[0].clone()
+    }
+
+    fn go(controller: &mut PriorityToRight, junction: &Junction, cars: &[Car], id: u32) -> bool {
+        let car = cars.iter().find(|c| c.id() == id).unwrap();
+        controller.may_enter(car, &car.next_crossing().unwrap(), &junction.context(0.0, cars))
+    }
+
+    #[test]
+    fn car_from_the_right_goes_first() {
+        let junction = Junction::new();
+        let mut controller = PriorityToRight::default();
+        // West is on the right of a car coming from the north
+        let cars = [approaching(&junction, 0, Direction::North, 0.5), approaching(&junction, 1, Direction::West, 0.5)];
+        assert!(!go(&mut controller, &junction, &cars, 0));
+        assert!(go(&mut controller, &junction, &cars, 1));
+        assert_eq!(controller.waiting_for(&cars[0], &cars[0].next_crossing().unwrap(), &junction.context(0.0, &cars)), vec![1]);
+    }
+
+    #[test]
+    fn car_from_the_right_far_off_is_not_waited_for() {
+        let junction = Junction::new();
+        let mut controller = PriorityToRight::default();
+        let cars = [approaching(&junction, 0, Direction::North, 0.5), junction.car(1, Direction::West, Direction::East)];
+        assert!(go(&mut controller, &junction, &cars, 0));
+    }
+
+    #[test]
+    fn stopped_car_from_the_right_is_not_waited_for() {
+        let junction = Junction::new();
+        let mut controller = PriorityToRight::default();
+        let mut cars = junction.queue(Direction::West, 1, 1);
+        cars.push(approaching(&junction, 0, Direction::North, 0.5));
+        assert!(go(&mut controller, &junction, &cars, 0));
+    }
+
+    #[test]
+    fn only_the_first_car_of_a_lane_is_waited_for() {
+        let junction = Junction::new();
+        let mut controller = PriorityToRight::default();
+        // A car standing at the line from the west, another one coming up behind it
+        let mut cars = junction.queue(Direction::West, 1, 1);
+        let mut behind = junction.car(2, Direction::West, Direction::East);
+        behind.set_held(true);
+        cars.push(behind);
+        drive(&mut cars, 1.0);
+        assert!(!cars[1].is_stopped());
+        cars.push(approaching(&junction, 0, Direction::North, 0.5));
+        assert!(go(&mut controller, &junction, &cars, 0));
+    }
+}
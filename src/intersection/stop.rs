@@ -0,0 +1,179 @@
+use crate::cars::Car;
+use crate::map::{Crossing, Movement};
+use super::{exit_blocked, stopped_at_line, Context, IntersectionController};
+
+/// Cars stopping at their line less than this apart arrive together, in seconds.
+const TIE_S: f64 = 0.5;
+
+/// Stop signs on every approach: each car comes to a full stop at its line, then cars go first
+/// come, first served, a car giving way to the one on its right when they stopped together.
+/// A car also goes while the ones before it wait if it conflicts with none of them, nor with
+/// the cars in the junction. A car with no room past the junction does not keep the cars
+/// after it waiting.
+#[derive(Debug,Clone,Default)]
+pub struct AllWayStop {
+    /// Cars standing at their line, with the time they stopped and their way through.
+    waiting: Vec<(u32, f64, Crossing)>,
+    /// Cars told to go and not out of the junction yet, with their way through it.
+    released: Vec<(u32, Crossing)>,
+}
+
+impl AllWayStop {
+    /// Indices in `waiting` in the order the cars may go. Of the cars that stopped within
+    /// `TIE_S` of the first one left, the first to go is one with none of them on its right,
+    /// or the first stopped if there is one on every side.
+    fn order(&self, ctx: &Context) -> Vec<usize> {
+        let mut left: Vec<usize> = (0..self.waiting.len()).collect();
+        left.sort_by(|&a, &b| self.waiting[a].1.total_cmp(&self.waiting[b].1).then(self.waiting[a].0.cmp(&self.waiting[b].0)));
+        let mut order = Vec::with_capacity(left.len());
+        while !left.is_empty() {
+            let first = self.waiting[left[0]].1;
+            let tied: Vec<usize> = left.iter().copied().take_while(|&i| self.waiting[i].1 - first < TIE_S).collect();
+            let next = tied.iter().position(|&i| {
+                let right = Movement::Right.exit(ctx.side(&self.waiting[i].2));
+                tied.iter().all(|&j| ctx.side(&self.waiting[j].2) != right)
+            }).unwrap_or(0);
+            order.push(left.remove(next));
+        }
+        order
+    }
+
+    /// Cars standing or going through before waiting car `car`, at `crossing`, that it conflicts
+    /// with. A car standing with no room past the junction lets the ones after it go meanwhile.
+    fn blocking(&self, car: u32, crossing: &Crossing, ctx: &Context) -> Vec<u32> {
+        let order = self.order(ctx);
+        let rank = order.iter().position(|&i| self.waiting[i].0 == car).unwrap_or(order.len());
+        let room = |id: u32, c: &Crossing| ctx.cars.iter().find(|o| o.id() == id).is_none_or(|o| exit_blocked(o, c, ctx.cars).is_none());
+        order[..rank].iter().map(|&i| (self.waiting[i].0, self.waiting[i].2))
+            .filter(|(id, c)| room(*id, c))
+            .chain(self.released.iter().copied())
+            .filter(|(_, c)| ctx.conflict(crossing, c))
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+impl IntersectionController for AllWayStop {
+    fn may_enter(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        if self.released.iter().any(|&(id, _)| id == car.id()) {
+            return true;
+        }
+        if !self.waiting.iter().any(|&(id, _, _)| id == car.id()) {
+            if !stopped_at_line(car, crossing) {
+                return false;
+            }
+            self.waiting.push((car.id(), ctx.time, *crossing));
+        }
+        let clear = self.blocking(car.id(), crossing, ctx).is_empty();
+        if clear {
+            self.waiting.retain(|&(id, _, _)| id != car.id());
+            self.released.push((car.id(), *crossing));
+        }
+        clear
+    }
+
+    fn waiting_for(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> Vec<u32> {
+        match self.waiting.iter().any(|&(id, _, _)| id == car.id()) {
+            true => self.blocking(car.id(), crossing, ctx),
+            false => Vec::new(),
+        }
+    }
+
+    /// Put `car` first in line, as if it had stopped before everybody else.
+    fn give_priority(&mut self, car: &Car, crossing: &Crossing, _ctx: &Context) -> bool {
+        if !stopped_at_line(car, crossing) {
+            return false;
+        }
+        let Some(entry) = self.waiting.iter_mut().find(|(id, _, _)| *id == car.id()) else { return false };
+        entry.1 = f64::MIN;
+        true
+    }
+
+    fn on_exit(&mut self, car: u32, _ctx: &Context) {
+        self.waiting.retain(|&(id, _, _)| id != car);
+        self.released.retain(|&(id, _)| id != car);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::Direction;
+    use super::super::testing::{drive, Junction};
+    use super::*;
+
+    fn go(controller: &mut AllWayStop, junction: &Junction, cars: &[Car], id: u32, time: f64) -> bool {
+        let car = cars.iter().find(|c| c.id() == id).unwrap();
+        controller.may_enter(car, &car.next_crossing().unwrap(), &junction.context(time, cars))
+    }
+
+    /// A car from every side, each standing at its line: north 0, east 1, south 2, west 3.
+    fn stopped(junction: &Junction) -> Vec<Car> {
+        [Direction::North, Direction::East, Direction::South, Direction::West].into_iter()
+            .zip(0..)
+            .flat_map(|(from, id)| junction.queue(from, 1, id))
+            .collect()
+    }
+
+    #[test]
+    fn cars_stop_before_going() {
+        let junction = Junction::new();
+        let mut controller = AllWayStop::default();
+        let mut cars = [junction.car(0, Direction::North, Direction::South)];
+        drive(&mut cars, 0.5);
+        assert!(!go(&mut controller, &junction, &cars, 0, 0.0));
+        cars[0].set_held(true);
+        drive(&mut cars, 5.0);
+        assert!(go(&mut controller, &junction, &cars, 0, 5.0));
+    }
+
+    #[test]
+    fn cars_go_in_the_order_they_stopped() {
+        let junction = Junction::new();
+        let mut controller = AllWayStop::default();
+        let cars = stopped(&junction);
+        assert!(go(&mut controller, &junction, &cars, 0, 0.0));
+        assert!(!go(&mut controller, &junction, &cars, 1, 1.0));
+        // South does not cross north, but crosses east which stopped before it
+        assert!(!go(&mut controller, &junction, &cars, 2, 2.0));
+        assert!(!go(&mut controller, &junction, &cars, 3, 3.0));
+        controller.on_exit(0, &junction.context(4.0, &cars));
+        assert!(!go(&mut controller, &junction, &cars, 2, 4.0));
+        assert!(go(&mut controller, &junction, &cars, 1, 4.0));
+        assert!(!go(&mut controller, &junction, &cars, 3, 4.0));
+        controller.on_exit(1, &junction.context(5.0, &cars));
+        assert!(!go(&mut controller, &junction, &cars, 3, 5.0));
+        assert!(go(&mut controller, &junction, &cars, 2, 5.0));
+        controller.on_exit(2, &junction.context(6.0, &cars));
+        assert!(go(&mut controller, &junction, &cars, 3, 6.0));
+    }
+
+    #[test]
+    fn car_crossing_nobody_before_it_goes_at_once() {
+        let junction = Junction::new();
+        let mut controller = AllWayStop::default();
+        let cars = stopped(&junction);
+        assert!(go(&mut controller, &junction, &cars, 0, 0.0));
+        assert!(go(&mut controller, &junction, &cars, 2, 1.0));
+    }
+
+    #[test]
+    fn car_on_the_right_goes_first_when_they_stopped_together() {
+        let junction = Junction::new();
+        for (west_stops, first) in [(TIE_S / 2.0, 3), (TIE_S * 2.0, 0)] {
+            let mut controller = AllWayStop::default();
+            let mut cars = stopped(&junction);
+            // A left turn from the east in the junction keeps both north and west waiting
+            let mut left = [junction.car(4, Direction::East, Direction::South)];
+            left[0].set_held(true);
+            drive(&mut left, 6.0);
+            cars.push(left[0].clone());
+            assert!(go(&mut controller, &junction, &cars, 4, -1.0));
+            assert!(!go(&mut controller, &junction, &cars, 0, 0.0));
+            assert!(!go(&mut controller, &junction, &cars, 3, west_stops));
+            controller.on_exit(4, &junction.context(3.0, &cars));
+            let second = if first == 0 { 3 } else { 0 };
+            assert!(!go(&mut controller, &junction, &cars, second, 3.0));
+            assert!(go(&mut controller, &junction, &cars, first, 3.0));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! The four-way junction of the default layout, for running controllers in unit tests.
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::cars::Car;
+use crate::map::{self, ConflictGrid, Direction, Layout, Network, Port};
+use crate::occupancy::{Occupancy, DEFAULT_LANE_CAP};
+use crate::signals::Detectors;
+use super::Context;
+
+const CAR: (u32, u32) = (32, 45);
+const DT: f64 = 1.0 / 60.0;
+
+/// What a controller of the junction reads, kept up to date by `observe`.
+pub struct Junction {
+    pub network: Network,
+    pub grid: ConflictGrid,
+    pub detectors: Detectors,
+    pub occupancy: Occupancy,
+}
+
+impl Junction {
+    pub fn new() -> Self {
+        let network = Network::single(Layout::load(map::DEFAULT_LAYOUT).unwrap());
+        Self {
+            grid: ConflictGrid::new(&network.nodes[0].layout, CAR),
+            detectors: Detectors::new(&network),
+            occupancy: Occupancy::new(&network, DEFAULT_LANE_CAP),
+            network,
+        }
+    }
+
+    /// Car `id` at the start of its route from the `from` side to the `to` side.
+    pub fn car(&self, id: u32, from: Direction, to: Direction) -> Car {
+        let mut rng = StdRng::seed_from_u64(0);
+        let route = self.network.route(Port { node: 0, side: from }, Port { node: 0, side: to }, &[], &mut rng).unwrap();
+        let mut car = map::spawn_car(route, CAR.0, CAR.1).unwrap();
+        car.set_id(id);
+        car
+    }
+
+    /// `n` cars from the `from` side to the opposite one, ids from `first` on, held and queued at
+    /// their stop line.
+    pub fn queue(&self, from: Direction, n: u32, first: u32) -> Vec<Car> {
+        let mut cars: Vec<Car> = Vec::new();
+        for id in first..first + n {
+            let mut car = self.car(id, from, from.opposite());
+            car.set_held(true);
+            cars.push(car);
+            drive(&mut cars, 1.0);
+        }
+        drive(&mut cars, 5.0);
+        cars
+    }
+
+    /// Read the loops and the lanes, as the simulation does after every step.
+    pub fn observe(&mut self, cars: &[Car]) {
+        self.detectors.update(cars);
+        self.occupancy.update(cars, &self.network);
+    }
+
+    pub fn context<'a>(&'a self, time: f64, cars: &'a [Car]) -> Context<'a> {
+        Context {
+            time,
+            cars,
+            network: &self.network,
+            grid: &self.grid,
+            detectors: &self.detectors,
+            occupancy: &self.occupancy,
+        }
+    }
+}
+
+/// Step `cars` for `seconds`, each seeing the others, held at their line or not as they are.
+pub fn drive(cars: &mut [Car], seconds: f64) {
+    for _ in 0..(seconds / DT).round() as usize {
+        let snapshot = cars.to_vec();
+        for car in cars.iter_mut() {
+            car.update(DT, &snapshot);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+//! Headless core of the intersection simulator: the map, the cars and the simulation stepping
+//! them. The SDL2 window lives in the binary, behind the `render` feature.
+
+pub mod cars;
+pub mod clock;
+pub mod deadlock;
+pub mod demand;
+pub mod geometry;
+pub mod intersection;
+pub mod map;
+pub mod occupancy;
+pub mod reservation;
+pub mod signals;
+pub mod simulation;
@@ -1,25 +1,25 @@
+#[cfg(feature = "render")]
 mod entities;
+#[cfg(feature = "render")]
 mod events;
-mod cars;
-mod map;
+#[cfg(feature = "render")]
+mod render;
 
-use std::time::Duration;
-use cars::UpdateState;
-use sdl2::{image::LoadTexture, pixels::Color, video::Window};
+use smart_road_remake::{demand, intersection, map, signals, simulation};
+use smart_road_remake::simulation::Simulation;
 
-use entities::Entity;
-
-const SCREEN_SIZE: (u32,u32) = (1100,1100);
-const COOLDOWN_MS: u64 = 450;
-const TICK_SPEED: u32 = 60;
-const VELOCITY_P_S:u32 = 240;
+/// Simulated seconds run by `--headless` when no `--ticks` is given.
+const HEADLESS_DURATION_S: f64 = 60.0;
+/// Time between two random cars asked for by `--headless` runs without a demand.
+const RANDOM_SPAWN_INTERVAL_S: f64 = 0.45;
 // const DEBUG: bool = true;
 
-fn init_window(sdl_context: sdl2::Sdl) -> Result<Window,String> {
+#[cfg(feature = "render")]
+fn init_window(sdl_context: sdl2::Sdl, size: (u32,u32)) -> Result<sdl2::video::Window,String> {
     let video_subsystem = sdl_context.video()?;
 
     let window = video_subsystem
-        .window("Affichage d'une partie de l'image", SCREEN_SIZE.0, SCREEN_SIZE.1)
+        .window("Affichage d'une partie de l'image", size.0, size.1)
         .position_centered()
         .build()
         .map_err(|e| e.to_string())?;
@@ -27,111 +27,168 @@ fn init_window(sdl_context: sdl2::Sdl) -> Result<Window,String> {
     Ok(window)
 }
 
+/// Run the simulation without opening a window, until `ticks` or a deadlock failing the run.
+/// Without a demand, a random car is asked for every `RANDOM_SPAWN_INTERVAL_S`.
+fn run_headless(sim: &mut Simulation, ticks: u64) {
+    let every = (RANDOM_SPAWN_INTERVAL_S / sim.config().dt).round().max(1.0) as u64;
+    while sim.tick() < ticks && sim.failed().is_none() {
+        if sim.demand().is_none() && sim.tick().is_multiple_of(every) {
+            sim.spawn_random_car();
+        }
+        sim.step();
+    }
+}
 
-fn main() -> Result<(), String> {
-    // let mut ctrl = entities::Controller::init();
+#[cfg(feature = "render")]
+fn run_window(sim: &mut Simulation) -> Result<(), String> {
+    use sdl2::image::LoadTexture;
+    use std::time::Instant;
+    use smart_road_remake::clock::SimClock;
 
     let sdl_context = sdl2::init()?;
-    let window = init_window(sdl_context.clone())?;
+    let window = init_window(sdl_context.clone(), sim.network().size)?;
     let mut canvas = window.into_canvas().present_vsync().build().map_err(|e| e.to_string())?;
     let mut event_pump = sdl_context.event_pump()?;
 
-    let mut cars: Vec<cars::Car> = Vec::new();
-    let mut traffic_lights = map::TrafficLight::reposition_for_entry_lanes();
-
-    let mut cooldown_now = std::time::Instant::now();
-    let mut tick_time = std::time::Instant::now();
-
-    let mut car_spawned = 0u32;
-    let mut car_passed: u32 = 0u32;
-
-    let mut collisions_count: u32 = 0u32;
-
+    let mut clock = SimClock::new(sim.config().dt);
+    let mut frame_time = Instant::now();
     let mut debug = false;
 
     let tc = canvas.texture_creator();
     let car_texture = tc.load_texture("assets/cars.png")?;
 
-
     'running: loop {
         match events::handle(&mut event_pump)
         {
-            events::Type::SpawnCar(from,to) => {
-                if cooldown_now.elapsed() >= Duration::from_millis(COOLDOWN_MS) {
-                    let mut tmp = map::spawn_car(from, to, 32,45).unwrap();
-                    tmp.set_texture(&car_texture);
-                    tmp.set_velocity(VELOCITY_P_S/TICK_SPEED);
-                    cars.push(tmp);
-                    cooldown_now = std::time::Instant::now();
-                    car_spawned += 1;
-                }
+            events::Type::SpawnCar(from) => {
+                sim.spawn_car_from(from);
+            },
+            events::Type::SpawnRandomCar => {
+                sim.spawn_random_car();
             },
             events::Type::Quit => break 'running,
             events::Type::ToggleDebug => {
                 debug= !debug;
             }
+            events::Type::NextMode => {
+                sim.set_mode(sim.mode().next());
+                println!("intersections: {:?}", sim.mode());
+            }
             _ => {},
         };
 
-        canvas.set_draw_color(sdl2::pixels::Color::BLACK);
-        canvas.clear();
-
-        map::load_map(&mut canvas)?;
-        // map::show_points(&mut canvas)?;
-
-
-        let cars_clone = cars.clone();
-        let mut to_remove = -1;
-        for (i,c) in cars.iter_mut().enumerate() {
-            let mut tmp = cars_clone.clone();
-            tmp.remove(i);
-            match  c.update(tmp, &traffic_lights) {
-                UpdateState::Finished => to_remove = i as i32,
-                UpdateState::Waiting => {
-                    match (debug,c.get_detections()) {
-                        (true,Some((l,u))) => {
-                            canvas.set_draw_color(Color::RED);
-                            canvas.draw_rect(l).unwrap();
-                            canvas.draw_rect(u).unwrap();
-                        },
-                        _ => {},
-                    };
-                },
-                UpdateState::Collided => {
-                    collisions_count += 1;
-                    to_remove = i as i32;
-                },
-                _ => {
-                    match (debug,c.get_detections()) {
-                        (true,Some((l,u))) => {
-                            canvas.set_draw_color(Color::GRAY);
-                            canvas.draw_rect(l).unwrap();
-                            canvas.draw_rect(u).unwrap();
-                        },
-                        _ => {},
-                    };
-                },
-            }
-            c.set_debug(debug);
-            c.display(&mut canvas).unwrap();
+        let elapsed = frame_time.elapsed().as_secs_f64();
+        frame_time = Instant::now();
+        for _ in 0..clock.advance(elapsed) {
+            sim.step();
         }
-
-        // Update and render traffic lights
-        for light in &mut traffic_lights {
-            light.update();
-            canvas.set_draw_color(if light.is_green() { Color::GREEN } else { Color::RED });
-            canvas.fill_rect(sdl2::rect::Rect::from_center(light.position, 20, 20))?;
+        if sim.failed().is_some() {
+            break 'running;
         }
-        if to_remove != -1 {
-            car_passed += 1;
-            cars.remove(to_remove as usize);
+        render::draw(&mut canvas, &car_texture, sim, clock.alpha(), debug)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "render"))]
+fn run_window(_sim: &mut Simulation) -> Result<(), String> {
+    Err(String::from("built without the `render` feature, run with --headless"))
+}
+
+fn main() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let mut config = simulation::Config::default();
+    let mut layout_path = map::DEFAULT_LAYOUT.to_string();
+    let mut network_path = None;
+    let mut demand_path = None;
+    let mut signals_path = None;
+    let mut headless = false;
+    let mut ticks = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => headless = true,
+            "--ticks" => {
+                ticks = Some(args.next().and_then(|t| t.parse().ok()).ok_or("--ticks expects a number")?);
+                headless = true;
+            },
+            "--dt" => {
+                config.dt = args.next().and_then(|t| t.parse().ok()).filter(|dt: &f64| *dt > 0.0)
+                    .ok_or("--dt expects a positive number of seconds")?;
+            },
+            "--map" => {
+                layout_path = args.next().ok_or("--map expects a file")?;
+            },
+            "--network" => {
+                network_path = Some(args.next().ok_or("--network expects a file")?);
+            },
+            "--demand" => {
+                demand_path = Some(args.next().ok_or("--demand expects a file")?);
+            },
+            "--signals" => {
+                signals_path = Some(args.next().ok_or("--signals expects a file")?);
+            },
+            "--lane-cap" => {
+                config.lane_cap = args.next().and_then(|t| t.parse().ok()).filter(|c: &usize| *c > 0)
+                    .ok_or("--lane-cap expects a positive number of cars")?;
+            },
+            "--control" => {
+                config.control = Some(args.next().ok_or("--control expects fixed, actuated or adaptive")?.parse()?);
+            },
+            "--intersection" => {
+                config.mode = args.next().ok_or("--intersection expects signals, reservation, all-way-stop or priority-to-right")?.parse()?;
+            },
+            "--on-deadlock" => {
+                config.on_deadlock = args.next().ok_or("--on-deadlock expects priority or abort")?.parse()?;
+            },
+            "--routing" => {
+                config.routing = args.next().ok_or("--routing expects free-flow or congested")?.parse()?;
+            },
+            "--seed" => {
+                config.seed = args.next().and_then(|t| t.parse().ok()).ok_or("--seed expects a number")?;
+            },
+            _ => return Err(format!("unknown argument `{}`", arg)),
         }
+    }
 
-        canvas.present();
-        ::std::thread::sleep(Duration::new(0, tick_time.elapsed().as_nanos() as u32 / TICK_SPEED));
-        tick_time = std::time::Instant::now();
+    println!("seed: {}", config.seed);
+    if config.mode != intersection::Mode::Signals {
+        println!("intersections: {:?}", config.mode);
+    }
+    let network = match network_path {
+        Some(path) => map::Network::load(&path)?,
+        None => map::Network::single(map::Layout::load(&layout_path)?),
+    };
+    let demand = demand_path.map(|path| demand::Demand::load(&path, &network)).transpose()?;
+    let plan = signals_path.map(|path| signals::SignalPlan::load(&path, &network)).transpose()?;
+    let mut sim = Simulation::new(config, network);
+    if let Some(plan) = plan {
+        sim.set_signal_plan(&plan)?;
+        println!("signals: {:?} control, {} s cycle", config.control.unwrap_or(plan.control), plan.cycle());
+    } else if let Some(control) = config.control {
+        println!("signals: {:?} control", control);
+    }
+    if let Some(demand) = demand {
+        println!("demand: {}", demand.name);
+        sim.set_demand(demand);
+    }
+    if headless {
+        let ticks = ticks.unwrap_or((HEADLESS_DURATION_S / config.dt).round() as u64);
+        run_headless(&mut sim, ticks);
+    } else {
+        run_window(&mut sim)?;
     }
 
-    println!("\ncar spawned : {}\ncar passed  : {}\ncollisions  : {}\n",car_passed,car_spawned,collisions_count);
+    let stats = sim.stats();
+    println!("\ncar spawned : {}\ncar passed  : {}\ncollisions  : {}\nunserved    : {}\nstopped     : {:.1} s per car\ndeadlocks   : {}",stats.spawned,stats.passed,stats.collisions,sim.unserved(),stats.stopped_per_car(),sim.deadlocks().len());
+    for d in sim.deadlocks() {
+        match d.released {
+            Some(car) => println!("  {:.1} s: cars {:?} waiting on each other, car {} given priority", d.time, d.cars, car),
+            None => println!("  {:.1} s: cars {:?} waiting on each other", d.time, d.cars),
+        }
+    }
+    println!();
+    if let Some(d) = sim.failed() {
+        return Err(format!("run failed: deadlock at {:.1} s between cars {:?}", d.time, d.cars));
+    }
     Ok(())
 }
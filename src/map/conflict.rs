@@ -0,0 +1,134 @@
+use crate::geometry::{Obb, Path, Rect, Vec2};
+use super::{Direction, Layout, Movement};
+
+/// Cells across one lane of a conflict zone.
+const CELLS_PER_LANE: f64 = 2.0;
+/// Distance between two footprints sampled along a route, in pixels.
+const SAMPLE_STEP: f64 = 4.0;
+
+/// A cell swept by a route, from the distance the car has driven past the stop line when it
+/// first covers the cell to the distance at which it leaves it. At a given speed profile these
+/// are the times the cell is occupied.
+#[derive(Debug,Clone,Copy)]
+pub struct CellSpan {
+    pub cell: usize,
+    pub enter: f64,
+    pub leave: f64,
+}
+
+/// Cells swept by a car driving through the junction on one of its routes.
+#[derive(Debug,Clone)]
+pub struct RouteCells {
+    /// Index of the approach in the layout, and side it comes from.
+    pub approach: usize,
+    pub from: Direction,
+    pub lane: usize,
+    pub to: Direction,
+    pub movement: Movement,
+    pub cells: Vec<CellSpan>,
+}
+
+/// Conflict zone of a junction cut into square cells, with the cells every route through the
+/// junction sweeps. Two routes conflict when they share a cell.
+#[derive(Debug,Clone)]
+pub struct ConflictGrid {
+    origin: Vec2,
+    cell: f64,
+    cols: usize,
+    rows: usize,
+    routes: Vec<RouteCells>,
+    /// Whether routes `i` and `j` share a cell, at `i * routes.len() + j`.
+    conflicts: Vec<bool>,
+}
+
+impl ConflictGrid {
+    /// Grid of `layout` swept by cars of `car` width and length.
+    pub fn new(layout: &Layout, car: (u32, u32)) -> Self {
+        let zone = layout.conflict_zone;
+        let cell = layout.lane_width as f64 / CELLS_PER_LANE;
+        let mut grid = Self {
+            origin: Vec2::new(zone.x as f64, zone.y as f64),
+            cell,
+            cols: (zone.w as f64 / cell).ceil() as usize,
+            rows: (zone.h as f64 / cell).ceil() as usize,
+            routes: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        for (approach, a) in layout.approaches.iter().enumerate() {
+            for lane in 0..a.lanes.len() {
+                for exit in &layout.exits {
+                    let Ok(route) = layout.route(a.from, lane, exit.to) else { continue };
+                    let crossing = route.crossings[0];
+                    let cells = grid.sweep(&route.path, crossing.stop, crossing.exit, car);
+                    grid.routes.push(RouteCells { approach, from: a.from, lane, to: exit.to, movement: crossing.movement, cells });
+                }
+            }
+        }
+        let n = grid.routes.len();
+        grid.conflicts = (0..n * n)
+            .map(|k| {
+                let (a, b) = (&grid.routes[k / n], &grid.routes[k % n]);
+                a.cells.iter().any(|x| b.cells.iter().any(|y| x.cell == y.cell))
+            })
+            .collect();
+        grid
+    }
+
+    /// Cells covered by a car of size `car` driving `path` from its front on the stop line at
+    /// arc length `stop` until its rear leaves the zone at `exit`.
+    fn sweep(&self, path: &Path, stop: f64, exit: f64, car: (u32, u32)) -> Vec<CellSpan> {
+        let (w, l) = (car.0 as f64, car.1 as f64);
+        let start = stop - l / 2.0;
+        let mut spans: Vec<CellSpan> = Vec::new();
+        let mut s = start;
+        while s <= exit + l / 2.0 {
+            let at = s.min(path.length());
+            let footprint = Obb::new(path.point_at(at), path.heading_at(at), l, w);
+            for cell in (0..self.cells()).filter(|&i| self.obb(i).intersects(&footprint)) {
+                let x = s - start;
+                match spans.iter_mut().find(|span| span.cell == cell) {
+                    Some(span) => span.leave = x,
+                    None => spans.push(CellSpan { cell, enter: x, leave: x }),
+                }
+            }
+            s += SAMPLE_STEP;
+        }
+        spans
+    }
+
+    /// Number of cells.
+    pub fn cells(&self) -> usize {
+        self.cols * self.rows
+    }
+
+    fn obb(&self, index: usize) -> Obb {
+        let (col, row) = (index % self.cols, index / self.cols);
+        let center = self.origin + Vec2::new((col as f64 + 0.5) * self.cell, (row as f64 + 0.5) * self.cell);
+        Obb::new(center, 0.0, self.cell, self.cell)
+    }
+
+    pub fn rect(&self, index: usize) -> Rect {
+        self.obb(index).aabb()
+    }
+
+    pub fn routes(&self) -> &[RouteCells] {
+        &self.routes
+    }
+
+    /// Route through approach `approach` of the layout, on lane `lane`, leaving towards `to`.
+    pub fn route(&self, approach: usize, lane: usize, to: Direction) -> Option<usize> {
+        self.routes.iter().position(|r| r.approach == approach && r.lane == lane && r.to == to)
+    }
+
+    /// Whether routes `a` and `b` share a cell.
+    pub fn conflict(&self, a: usize, b: usize) -> bool {
+        self.conflicts[a * self.routes.len() + b]
+    }
+
+    /// Whether a movement from `a` conflicts with one from `b`, on any of their lanes.
+    pub fn movements_conflict(&self, a: (Direction, Movement), b: (Direction, Movement)) -> bool {
+        let of = |(from, movement): (Direction, Movement)| (0..self.routes.len())
+            .filter(move |&i| self.routes[i].from == from && self.routes[i].movement == movement);
+        of(a).any(|i| of(b).any(|j| self.conflict(i, j)))
+    }
+}
@@ -0,0 +1,291 @@
+use serde::Deserialize;
+use crate::geometry::{Path, Point, Rect, Vec2};
+use super::{turn, Direction, Lane, LaneChange, Movement, TrafficLightState};
+
+/// Where the intersection description is read from when none is given.
+pub const DEFAULT_LAYOUT: &str = "assets/intersection.toml";
+
+/// Lane of an approach, ending on the stop line, and the movements allowed from it.
+#[derive(Debug,Clone)]
+pub struct ApproachLane {
+    pub lane: Lane,
+    pub movements: Vec<Movement>,
+}
+
+/// Road bringing cars coming from `from` up to the junction.
+/// Lanes are listed from the centre of the road to the kerb.
+#[derive(Debug,Clone)]
+pub struct Approach {
+    pub from: Direction,
+    pub lanes: Vec<ApproachLane>,
+    /// Where the light guarding this approach stands, and its state at start.
+    pub light: Point,
+    pub initial: TrafficLightState,
+}
+
+/// Road taking cars from the conflict zone out towards `to`, lanes starting at
+/// the edge of the zone and listed from the centre of the road to the kerb.
+#[derive(Debug,Clone)]
+pub struct Exit {
+    pub to: Direction,
+    pub lanes: Vec<Lane>,
+}
+
+/// The stretch of a path a car spends inside an intersection.
+#[derive(Debug,Clone,Copy)]
+pub struct Crossing {
+    /// Index of the junction in the network.
+    pub node: usize,
+    /// Index of the traffic light guarding the approach, among all the lights of the network.
+    pub light: usize,
+    /// Index of the approach lane used, from the centre of the road.
+    pub lane: usize,
+    /// Side of the junction the path leaves by.
+    pub to: Direction,
+    /// Arc length of the stop line along the path.
+    pub stop: f64,
+    /// Arc length at which the path leaves the conflict zone.
+    pub exit: f64,
+    pub movement: Movement,
+}
+
+/// A path through one or more intersections and where it crosses each of them, in order.
+#[derive(Debug,Clone)]
+pub struct Route {
+    pub path: Path,
+    pub crossings: Vec<Crossing>,
+    /// Where the path moves to another lane along the links, in order.
+    pub lane_changes: Vec<LaneChange>,
+}
+
+/// Geometry of a junction, loaded from a TOML description.
+#[derive(Debug,Clone)]
+pub struct Layout {
+    pub name: String,
+    pub size: (u32,u32),
+    pub lane_width: u32,
+    pub conflict_zone: Rect,
+    pub approaches: Vec<Approach>,
+    pub exits: Vec<Exit>,
+    /// Every `(from, to)` pair served by at least one lane.
+    pub routes: Vec<(Direction,Direction)>,
+}
+
+// What the file looks like, turned into a `Layout` by `Layout::parse`.
+
+#[derive(Deserialize)]
+struct LaneFile {
+    start: [f64; 2],
+    end: [f64; 2],
+}
+
+#[derive(Deserialize)]
+struct LightFile {
+    position: [i32; 2],
+    state: TrafficLightState,
+}
+
+#[derive(Deserialize)]
+struct ApproachLaneFile {
+    start: [f64; 2],
+    end: [f64; 2],
+    movements: Vec<Movement>,
+}
+
+#[derive(Deserialize)]
+struct ApproachFile {
+    from: Direction,
+    lanes: Vec<ApproachLaneFile>,
+    light: LightFile,
+}
+
+#[derive(Deserialize)]
+struct ExitFile {
+    to: Direction,
+    lanes: Vec<LaneFile>,
+}
+
+#[derive(Deserialize)]
+struct ZoneFile {
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct LayoutFile {
+    name: String,
+    size: [u32; 2],
+    lane_width: u32,
+    conflict_zone: ZoneFile,
+    approaches: Vec<ApproachFile>,
+    exits: Vec<ExitFile>,
+}
+
+fn lane(start: [f64; 2], end: [f64; 2]) -> Lane {
+    Lane {
+        start: Vec2::new(start[0], start[1]),
+        end: Vec2::new(end[0], end[1]),
+    }
+}
+
+impl Layout {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: LayoutFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut layout = Self {
+            name: file.name,
+            size: (file.size[0], file.size[1]),
+            lane_width: file.lane_width,
+            conflict_zone: Rect::new(file.conflict_zone.x, file.conflict_zone.y, file.conflict_zone.w, file.conflict_zone.h),
+            approaches: file.approaches.into_iter().map(|a| Approach {
+                from: a.from,
+                lanes: a.lanes.into_iter().map(|l| ApproachLane {
+                    lane: lane(l.start, l.end),
+                    movements: l.movements,
+                }).collect(),
+                light: Point::new(a.light.position[0], a.light.position[1]),
+                initial: a.light.state,
+            }).collect(),
+            exits: file.exits.into_iter().map(|e| Exit {
+                to: e.to,
+                lanes: e.lanes.into_iter().map(|l| lane(l.start, l.end)).collect(),
+            }).collect(),
+            routes: Vec::new(),
+        };
+        layout.find_routes()?;
+        Ok(layout)
+    }
+
+    /// Fill `routes` from the movements allowed on the approach lanes.
+    pub(super) fn find_routes(&mut self) -> Result<(), String> {
+        if self.lane_width == 0 || self.conflict_zone.w == 0 || self.conflict_zone.h == 0 {
+            return Err(String::from("lane_width and the conflict zone need a positive size"));
+        }
+        if self.approaches.iter().any(|a| a.lanes.is_empty()) || self.exits.iter().any(|e| e.lanes.is_empty()) {
+            return Err(String::from("every approach and exit needs at least one lane"));
+        }
+        self.routes.clear();
+        for approach in &self.approaches {
+            for exit in &self.exits {
+                if !self.lanes_for(approach.from, exit.to).is_empty() {
+                    self.routes.push((approach.from, exit.to));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn approach(&self, from: Direction) -> Result<(usize,&Approach), String> {
+        self.approaches.iter().enumerate().find(|(_,a)| a.from == from)
+            .ok_or_else(|| format!("no approach from {:?}", from))
+    }
+
+    pub fn exit(&self, to: Direction) -> Result<&Exit, String> {
+        self.exits.iter().find(|e| e.to == to)
+            .ok_or_else(|| format!("no exit towards {:?}", to))
+    }
+
+    /// Where cars coming from `from` may go.
+    pub fn destinations(&self, from: Direction) -> Vec<Direction> {
+        self.routes.iter().filter(|(f,_)| *f == from).map(|(_,t)| *t).collect()
+    }
+
+    /// Exit lane reached from the `lane`-th lane of an approach: the same rank from the
+    /// centre of the road, or the kerb lane if the exit has fewer lanes.
+    fn exit_lane(exit: &Exit, lane: usize) -> Lane {
+        exit.lanes[lane.min(exit.lanes.len() - 1)]
+    }
+
+    /// Indices of the lanes of the `from` approach whose markings allow going to `to`.
+    pub fn lanes_for(&self, from: Direction, to: Direction) -> Vec<usize> {
+        let (Ok((_, approach)), Ok(exit)) = (self.approach(from), self.exit(to)) else {
+            return Vec::new();
+        };
+        approach.lanes.iter().enumerate()
+            .filter(|(i, l)| Movement::between(l.lane, Self::exit_lane(exit, *i))
+                .is_some_and(|m| l.movements.contains(&m)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Entry and exit lanes used by a car on the `lane`-th lane of the `from` approach going to `to`,
+    /// with the index of the approach and the movement made.
+    pub fn lanes(&self, from: Direction, lane: usize, to: Direction) -> Result<(usize, Lane, Lane, Movement), String> {
+        if !self.lanes_for(from, to).contains(&lane) {
+            return Err(String::from("invalid direction combination"));
+        }
+        let (index, approach) = self.approach(from)?;
+        let entry = approach.lanes[lane].lane;
+        let exit = Self::exit_lane(self.exit(to)?, lane);
+        let movement = Movement::between(entry, exit).ok_or("U-turns are not supported")?;
+        Ok((index, entry, exit, movement))
+    }
+
+    /// Path from the outer end of the `lane`-th lane of the `from` approach to the outer end of the `to` exit.
+    pub fn route(&self, from: Direction, lane: usize, to: Direction) -> Result<Route, String> {
+        let (light, entry, exit, movement) = self.lanes(from, lane, to)?;
+
+        let mut points = vec![entry.start, entry.end];
+        points.extend(turn(entry, exit));
+        let path = Path::new(points);
+        let stop = (entry.end - entry.start).length();
+        let crossing = Crossing { node: 0, light, lane, to, stop, exit: path.length(), movement };
+
+        let mut points = path.points().to_vec();
+        points.push(exit.end);
+        Ok(Route { path: Path::new(points), crossings: vec![crossing], lane_changes: Vec::new() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn junction() -> String {
+        std::fs::read_to_string(DEFAULT_LAYOUT).unwrap()
+    }
+
+    #[test]
+    fn default_layout_serves_every_turn() {
+        let layout = Layout::parse(&junction()).unwrap();
+        assert_eq!(layout.approaches.len(), 4);
+        assert_eq!(layout.routes.len(), 12);
+        assert_eq!(layout.destinations(Direction::North), vec![Direction::South, Direction::East, Direction::West]);
+        let route = layout.route(Direction::West, 0, Direction::North).unwrap();
+        assert_eq!(route.crossings[0].movement, Movement::Left);
+        assert!((route.crossings[0].stop - 510.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_lane_width_is_rejected() {
+        let text = junction().replace("lane_width = 100", "lane_width = 0");
+        assert!(Layout::parse(&text).is_err());
+    }
+
+    #[test]
+    fn empty_conflict_zone_is_rejected() {
+        let text = junction().replace("w = 140", "w = 0");
+        assert!(Layout::parse(&text).is_err());
+    }
+
+    #[test]
+    fn approach_without_lanes_is_rejected() {
+        let text = junction().replacen("lanes = [{ start = [490, -40], end = [490, 470], movements = [\"left\", \"straight\", \"right\"] }]", "lanes = []", 1);
+        assert_ne!(text, junction());
+        assert!(Layout::parse(&text).is_err());
+    }
+
+    #[test]
+    fn lane_markings_restrict_the_movements() {
+        let text = junction().replacen("movements = [\"left\", \"straight\", \"right\"]", "movements = [\"straight\"]", 1);
+        let layout = Layout::parse(&text).unwrap();
+        assert_eq!(layout.destinations(Direction::North), vec![Direction::South]);
+        assert!(layout.route(Direction::North, 0, Direction::East).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+mod conflict;
+mod layout;
+mod network;
+mod routing;
+
+use serde::Deserialize;
+use crate::cars::{Car, Rgb};
+use crate::geometry::{cubic_bezier, Point, Vec2};
+
+pub use conflict::*;
+pub use layout::*;
+pub use network::*;
+pub use routing::*;
+
+/// Tangent length of a cubic Bézier approximating a quarter circle, relative to the radius.
+const BEZIER_KAPPA: f64 = 0.5523;
+/// Number of straight pieces a turn is sampled into.
+const TURN_SEGMENTS: usize = 16;
+
+/// A straight piece of lane, driven from `start` to `end`.
+#[derive(Debug,Clone,Copy)]
+pub struct Lane {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
+impl Lane {
+    pub fn direction(&self) -> Vec2 {
+        (self.end - self.start).normalized()
+    }
+}
+
+/// What a car does at the junction, seen from its approach lane.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Movement {
+    Left,
+    Straight,
+    Right,
+}
+
+impl Movement {
+    pub const ALL: [Movement; 3] = [Movement::Left, Movement::Straight, Movement::Right];
+
+    /// Position in `Movement::ALL`.
+    pub fn index(self) -> usize {
+        match self {
+            Movement::Left => 0,
+            Movement::Straight => 1,
+            Movement::Right => 2,
+        }
+    }
+
+    /// Side of the junction a car coming from `from` leaves by.
+    pub fn exit(self, from: Direction) -> Direction {
+        let heading = -from.outward();
+        // Lanes are on the right of the heading, y growing downwards
+        let out = match self {
+            Movement::Straight => heading,
+            Movement::Right => heading.perpendicular(),
+            Movement::Left => -heading.perpendicular(),
+        };
+        Direction::ALL.into_iter().find(|d| d.outward().dot(out) > 0.5).unwrap_or(from.opposite())
+    }
+
+    /// Movement of a car driving `entry` then `exit`, `None` for a U-turn.
+    pub fn between(entry: Lane, exit: Lane) -> Option<Self> {
+        let (d0, d1) = (entry.direction(), exit.direction());
+        if d0.dot(d1) > 0.7 {
+            return Some(Movement::Straight);
+        }
+        if d0.dot(d1) < -0.7 {
+            return None;
+        }
+        // y grows downwards, so a left turn has a negative cross product
+        match d0.x * d1.y - d0.y * d1.x < 0.0 {
+            true => Some(Movement::Left),
+            false => Some(Movement::Right),
+        }
+    }
+}
+
+/// Points of the way through the intersection from the end of `entry` to the start of `exit`:
+/// a straight line, or a Bézier curve tangent to both lanes for turns.
+fn turn(entry: Lane, exit: Lane) -> Vec<Vec2> {
+    let (p0, p3) = (entry.end, exit.start);
+    let (d0, d1) = (entry.direction(), exit.direction());
+    if d0.dot(d1) > 0.99 {
+        return vec![p3];
+    }
+    let chord = p3 - p0;
+    let k0 = chord.dot(d0).abs() * BEZIER_KAPPA;
+    let k1 = chord.dot(d1).abs() * BEZIER_KAPPA;
+    cubic_bezier(p0, p0 + d0 * k0, p3 - d1 * k1, p3, TURN_SEGMENTS)
+}
+
+#[derive(Debug,PartialEq,Eq,Hash,Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    North,
+    South,
+    West,
+    East,
+}
+
+/// What a signal head shows, from the most to the least restrictive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrafficLightState {
+    Red,
+    /// Stop if you can do so comfortably, the movement is about to turn red.
+    Yellow,
+    /// Green ball: go, giving way to the conflicting movements released at the same time.
+    Permissive,
+    /// Protected green, no conflicting movement is released.
+    Green,
+}
+
+/// Signal heads of one approach, one per movement, set by the signal controller of its junction.
+#[derive(Debug,Clone)]
+pub struct TrafficLight {
+    pub position: Point,
+    /// Side of the junction the approach comes from.
+    pub from: Direction,
+    /// State for each movement, indexed by `Movement::index`.
+    states: [TrafficLightState; 3],
+    /// Whether right-turning cars may go on red after stopping, when the way is clear.
+    pub right_on_red: bool,
+}
+
+impl TrafficLight {
+    /// One light per approach of `layout`, in the same order, red until a controller sets them.
+    pub fn reposition_for_entry_lanes(layout: &Layout) -> Vec<Self> {
+        layout.approaches.iter()
+            .map(|a| TrafficLight::new(a.light, a.from))
+            .collect()
+    }
+
+    pub fn new(position: Point, from: Direction) -> Self {
+        Self {
+            position,
+            from,
+            states: [TrafficLightState::Red; 3],
+            right_on_red: false,
+        }
+    }
+
+    pub fn state(&self, movement: Movement) -> TrafficLightState {
+        self.states[movement.index()]
+    }
+
+    pub fn set(&mut self, movement: Movement, state: TrafficLightState) {
+        self.states[movement.index()] = state;
+    }
+}
+
+impl std::str::FromStr for Movement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Movement::Left),
+            "straight" => Ok(Movement::Straight),
+            "right" => Ok(Movement::Right),
+            _ => Err(format!("`{}` is not a movement, expected left, straight or right", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "north" => Ok(Direction::North),
+            "south" => Ok(Direction::South),
+            "east" => Ok(Direction::East),
+            "west" => Ok(Direction::West),
+            _ => Err(format!("`{}` is not a side, expected north, south, east or west", s)),
+        }
+    }
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+
+    /// Unit vector pointing from a junction towards this side of it.
+    pub fn outward(self) -> Vec2 {
+        match self {
+            Direction::North => Vec2::new(0.0, -1.0),
+            Direction::South => Vec2::new(0.0, 1.0),
+            Direction::East => Vec2::new(1.0, 0.0),
+            Direction::West => Vec2::new(-1.0, 0.0),
+        }
+    }
+
+    /// Position in `Direction::ALL`.
+    pub fn index(self) -> usize {
+        match self {
+            Direction::North => 0,
+            Direction::South => 1,
+            Direction::East => 2,
+            Direction::West => 3,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+        }
+    }
+}
+
+/// Car at the start of `route`, coloured after its first movement.
+pub fn spawn_car(route: Route, car_w: u32, car_l: u32) -> Result<Car, String> {
+    let first = route.crossings.first().ok_or("route crosses no junction")?;
+    let color = match first.movement {
+        Movement::Right => Rgb(255, 255, 0),
+        Movement::Left => Rgb(255, 0, 0),
+        Movement::Straight => Rgb(0, 0, 255),
+    };
+    let mut car = Car::new(route.path.point_at(0.0).to_point(), car_w, car_l, color);
+
+    car.set_route(route);
+    Ok(car)
+}
@@ -0,0 +1,328 @@
+use serde::Deserialize;
+
+use crate::geometry::{cubic_bezier, Path, Rect, Vec2};
+use super::{turn, Approach, ApproachLane, Crossing, Direction, Exit, Lane, Layout, Movement, Route, TrafficLightState, TURN_SEGMENTS};
+
+/// How far past the edge of the canvas the roads leaving the network go, so cars appear and vanish off screen.
+const OFF_SCREEN: f64 = 40.0;
+/// Share of the length of a link a lane change is spread over.
+const LANE_CHANGE_SPAN: f64 = 0.4;
+/// Earliest and latest share of the length of a link a lane change may start at.
+const LANE_CHANGE_WINDOW: (f64, f64) = (0.1, 0.5);
+
+/// A junction of the network and its geometry.
+#[derive(Debug,Clone)]
+pub struct Node {
+    pub name: String,
+    pub layout: Layout,
+    /// Index of the light of the first approach of `layout` among all the lights of the network.
+    pub first_light: usize,
+}
+
+/// One-way road from a side of a junction to the facing side of the next one.
+#[derive(Debug,Clone,Copy)]
+pub struct Link {
+    pub from: usize,
+    /// Side of `from` the link leaves by, it reaches `to` on the opposite side.
+    pub side: Direction,
+    pub to: usize,
+    /// Distance between the two conflict zones, in pixels.
+    pub length: f64,
+    pub lanes: usize,
+}
+
+/// Side of a junction where cars enter or leave the network.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Port {
+    pub node: usize,
+    pub side: Direction,
+}
+
+/// One junction crossed by a route: entered from `from` on approach lane `lane`, left towards `to`.
+#[derive(Debug,Clone,Copy)]
+pub struct Hop {
+    pub node: usize,
+    pub from: Direction,
+    pub lane: usize,
+    pub to: Direction,
+}
+
+/// Junctions connected by links. Sides of junctions without a link are the entries and exits of the network.
+#[derive(Debug,Clone)]
+pub struct Network {
+    pub name: String,
+    pub size: (u32,u32),
+    pub nodes: Vec<Node>,
+    pub links: Vec<Link>,
+    pub entries: Vec<Port>,
+    pub exits: Vec<Port>,
+}
+
+// What the file looks like, turned into a `Network` by `Network::parse`.
+
+#[derive(Deserialize)]
+struct NodeFile {
+    name: String,
+    center: [f64; 2],
+}
+
+#[derive(Deserialize)]
+struct RoadFile {
+    between: [String; 2],
+    lanes: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct NetworkFile {
+    name: String,
+    size: [u32; 2],
+    lane_width: u32,
+    /// Lanes in each direction of the roads not given their own count.
+    lanes: Option<usize>,
+    nodes: Vec<NodeFile>,
+    #[serde(default)]
+    roads: Vec<RoadFile>,
+}
+
+/// What lies on one side of a junction: a road to another junction or to the edge of the canvas.
+#[derive(Clone,Copy)]
+struct Side {
+    neighbour: Option<usize>,
+    lanes: usize,
+}
+
+/// Movements allowed from the `lane`-th of `lanes` approach lanes: everything on a single lane,
+/// otherwise left from the inner lane and right from the outer one.
+fn movements(lane: usize, lanes: usize) -> Vec<Movement> {
+    match lane {
+        _ if lanes == 1 => vec![Movement::Left, Movement::Straight, Movement::Right],
+        0 => vec![Movement::Left, Movement::Straight],
+        l if l == lanes - 1 => vec![Movement::Straight, Movement::Right],
+        _ => vec![Movement::Straight],
+    }
+}
+
+/// A move from lane `from` to the parallel lane `to` along a link. It is spread over the same
+/// distance wherever it starts, so putting it off leaves the arc lengths past it unchanged.
+#[derive(Debug,Clone,Copy)]
+pub struct LaneChange {
+    pub from: Lane,
+    pub to: Lane,
+    /// Arc length of the start of the link along the path.
+    pub origin: f64,
+    /// Arc length at which the car leaves `from`.
+    pub start: f64,
+    /// Furthest `start` can be put off to.
+    pub latest: f64,
+    /// Length of the path of the move.
+    pub length: f64,
+}
+
+impl LaneChange {
+    /// Move from `from` to `to` as early as it may start, on a link starting at arc length
+    /// `origin`. `None` if they are the same lane.
+    fn new(from: Lane, to: Lane, origin: f64) -> Option<Self> {
+        if (to.start - from.start).length() < 1.0 {
+            return None;
+        }
+        let link = (to.end - to.start).length();
+        let mut change = Self { from, to, origin, start: origin + link * LANE_CHANGE_WINDOW.0, latest: origin + link * LANE_CHANGE_WINDOW.1, length: 0.0 };
+        change.length = Path::new(change.points(change.start)).length();
+        Some(change)
+    }
+
+    /// Points of the move when it starts at arc length `start`.
+    pub fn points(&self, start: f64) -> Vec<Vec2> {
+        let dir = self.to.direction();
+        let along = start - self.origin;
+        let span = (self.to.end - self.to.start).length() * LANE_CHANGE_SPAN;
+        let a = self.from.start + dir * along;
+        let b = self.to.start + dir * (along + span);
+        cubic_bezier(a, a + dir * (span / 3.0), b - dir * (span / 3.0), b, TURN_SEGMENTS)
+    }
+}
+
+impl Network {
+    /// A network made of the single junction `layout`.
+    pub fn single(layout: Layout) -> Self {
+        let entries = layout.approaches.iter().map(|a| Port { node: 0, side: a.from }).collect();
+        let exits = layout.exits.iter().map(|e| Port { node: 0, side: e.to }).collect();
+        Self {
+            name: layout.name.clone(),
+            size: layout.size,
+            nodes: vec![Node { name: layout.name.clone(), layout, first_light: 0 }],
+            links: Vec::new(),
+            entries,
+            exits,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: NetworkFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let default_lanes = file.lanes.unwrap_or(1);
+        let lane_width = file.lane_width as f64;
+        let centers: Vec<Vec2> = file.nodes.iter().map(|n| Vec2::new(n.center[0], n.center[1])).collect();
+        let index = |name: &str| file.nodes.iter().position(|n| n.name == name)
+            .ok_or_else(|| format!("no junction named `{}`", name));
+
+        let mut sides: Vec<[Option<Side>; 4]> = vec![[None; 4]; file.nodes.len()];
+        for road in &file.roads {
+            let (a, b) = (index(&road.between[0])?, index(&road.between[1])?);
+            let delta = centers[b] - centers[a];
+            let side = match (delta.x.abs() < 1e-6, delta.y.abs() < 1e-6) {
+                (true, false) if delta.y < 0.0 => Direction::North,
+                (true, false) => Direction::South,
+                (false, true) if delta.x > 0.0 => Direction::East,
+                (false, true) => Direction::West,
+                _ => return Err(format!("road {} - {} is not horizontal or vertical", road.between[0], road.between[1])),
+            };
+            let lanes = road.lanes.unwrap_or(default_lanes);
+            for (node, side, neighbour) in [(a, side, b), (b, side.opposite(), a)] {
+                let slot = &mut sides[node][side.index()];
+                if slot.is_some() {
+                    return Err(format!("two roads on the {:?} side of `{}`", side, file.nodes[node].name));
+                }
+                *slot = Some(Side { neighbour: Some(neighbour), lanes });
+            }
+        }
+        let sides: Vec<[Side; 4]> = sides.into_iter()
+            .map(|s| s.map(|s| s.unwrap_or(Side { neighbour: None, lanes: default_lanes })))
+            .collect();
+        if sides.iter().flatten().any(|s| s.lanes == 0) {
+            return Err(String::from("roads need at least one lane"));
+        }
+
+        // Square conflict zones, as wide as the widest road crossing them
+        let half: Vec<f64> = sides.iter()
+            .map(|s| s.iter().map(|s| s.lanes).max().unwrap_or(1) as f64 * lane_width)
+            .collect();
+
+        let mut network = Self {
+            name: file.name,
+            size: (file.size[0], file.size[1]),
+            nodes: Vec::new(),
+            links: Vec::new(),
+            entries: Vec::new(),
+            exits: Vec::new(),
+        };
+        let mut first_light = 0;
+        for (n, node) in file.nodes.iter().enumerate() {
+            let (c, h) = (centers[n], half[n]);
+            let mut layout = Layout {
+                name: node.name.clone(),
+                size: network.size,
+                lane_width: file.lane_width,
+                conflict_zone: Rect::new((c.x - h).round() as i32, (c.y - h).round() as i32, (2.0 * h) as u32, (2.0 * h) as u32),
+                approaches: Vec::new(),
+                exits: Vec::new(),
+                routes: Vec::new(),
+            };
+            for (&side, s) in Direction::ALL.iter().zip(sides[n]) {
+                let out = side.outward();
+                // Distance from the centre to the far end of the road on this side
+                let reach = match s.neighbour {
+                    Some(m) => (centers[m] - c).length() - half[m],
+                    None => OFF_SCREEN + match side {
+                        Direction::North => c.y,
+                        Direction::South => network.size.1 as f64 - c.y,
+                        Direction::East => network.size.0 as f64 - c.x,
+                        Direction::West => c.x,
+                    },
+                };
+                if reach <= h {
+                    return Err(format!("the {:?} road of `{}` is too short", side, node.name));
+                }
+                match s.neighbour {
+                    Some(m) => network.links.push(Link { from: n, side, to: m, length: reach - h, lanes: s.lanes }),
+                    None => {
+                        network.entries.push(Port { node: n, side });
+                        network.exits.push(Port { node: n, side });
+                    },
+                }
+
+                // Traffic drives on the right: inbound lanes on the right of `-out`, outbound on the right of `out`
+                let inbound = (-out).perpendicular();
+                let outbound = out.perpendicular();
+                let offset = |i: usize| (i as f64 + 0.5) * lane_width;
+                layout.approaches.push(Approach {
+                    from: side,
+                    lanes: (0..s.lanes).map(|i| ApproachLane {
+                        lane: Lane { start: c + out * reach + inbound * offset(i), end: c + out * h + inbound * offset(i) },
+                        movements: movements(i, s.lanes),
+                    }).collect(),
+                    light: (c + out * h + inbound * h).to_point(),
+                    initial: match side {
+                        Direction::North | Direction::South => TrafficLightState::Green,
+                        Direction::East | Direction::West => TrafficLightState::Red,
+                    },
+                });
+                layout.exits.push(Exit {
+                    to: side,
+                    lanes: (0..s.lanes).map(|i| Lane { start: c + out * h + outbound * offset(i), end: c + out * reach + outbound * offset(i) }).collect(),
+                });
+            }
+            layout.find_routes()?;
+            let lights = layout.approaches.len();
+            network.nodes.push(Node { name: node.name.clone(), layout, first_light });
+            first_light += lights;
+        }
+        Ok(network)
+    }
+
+    /// Link leaving junction `node` by its `side` side.
+    pub fn link(&self, node: usize, side: Direction) -> Option<&Link> {
+        self.links.iter().find(|l| l.from == node && l.side == side)
+    }
+
+    /// Link arriving at junction `node` on its `side` side.
+    pub fn link_into(&self, node: usize, side: Direction) -> Option<usize> {
+        self.links.iter().position(|l| l.to == node && l.side == side.opposite())
+    }
+
+    /// Junction and approach guarded by light `light`.
+    pub fn approach_by_light(&self, light: usize) -> Option<(usize, &Approach)> {
+        self.nodes.iter().enumerate().find_map(|(n, node)| {
+            node.layout.approaches.get(light.checked_sub(node.first_light)?).map(|a| (n, a))
+        })
+    }
+
+    /// Light guarding the approach of junction `node` from `side`, among all the lights of the network.
+    pub fn light(&self, node: usize, side: Direction) -> Option<usize> {
+        let node = self.nodes.get(node)?;
+        node.layout.approach(side).ok().map(|(index, _)| node.first_light + index)
+    }
+
+    /// Route driving through `hops` in order, changing lane along the links where needed.
+    pub fn path(&self, hops: &[Hop]) -> Result<Route, String> {
+        let mut points = Vec::new();
+        let mut crossings = Vec::new();
+        let mut lane_changes = Vec::new();
+        let mut previous: Option<Lane> = None;
+        for hop in hops {
+            let node = self.nodes.get(hop.node).ok_or("no such junction")?;
+            let (approach, entry, exit, movement) = node.layout.lanes(hop.from, hop.lane, hop.to)?;
+            match previous {
+                None => points.push(entry.start),
+                Some(lane) => {
+                    if let Some(change) = LaneChange::new(lane, entry, Path::new(points.clone()).length()) {
+                        points.extend(change.points(change.start));
+                        lane_changes.push(change);
+                    }
+                },
+            }
+            points.push(entry.end);
+            let stop = Path::new(points.clone()).length();
+            points.extend(turn(entry, exit));
+            let exit_at = Path::new(points.clone()).length();
+            crossings.push(Crossing { node: hop.node, light: node.first_light + approach, lane: hop.lane, to: hop.to, stop, exit: exit_at, movement });
+            previous = Some(exit);
+        }
+        points.push(previous.ok_or("empty route")?.end);
+        Ok(Route { path: Path::new(points), crossings, lane_changes })
+    }
+}
@@ -0,0 +1,235 @@
+use std::str::FromStr;
+
+use rand::Rng;
+
+use crate::cars::{BASE_VELOCITY, TURN_VELOCITY};
+use crate::geometry::Path;
+use super::{turn, Direction, Hop, Network, Port, Route};
+
+/// Spacing of cars stopped in a queue, front to front, in pixels. Sets the jam density of a lane.
+const JAM_SPACING: f64 = 60.0;
+/// Lowest speed on a congested link, as a fraction of the free-flow speed, so that a jammed link keeps a finite cost.
+const MIN_SPEED_RATIO: f64 = 0.1;
+
+/// What routes are weighed by.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Routing {
+    /// Travel time on empty roads.
+    FreeFlow,
+    /// Travel time with the speed on every link lowered by the cars currently on it.
+    Congested,
+}
+
+impl FromStr for Routing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "free-flow" => Ok(Routing::FreeFlow),
+            "congested" => Ok(Routing::Congested),
+            _ => Err(format!("unknown routing `{}`, expected free-flow or congested", s)),
+        }
+    }
+}
+
+impl Network {
+    /// Seconds needed to drive down link `link` with `cars` already on it. The speed drops
+    /// linearly with the density of cars (Greenshields), down to `MIN_SPEED_RATIO` of the free-flow speed.
+    pub fn link_time(&self, link: usize, cars: usize) -> f64 {
+        let link = &self.links[link];
+        let jam = link.lanes as f64 * link.length / JAM_SPACING;
+        let free_flow = link.length / BASE_VELOCITY;
+        free_flow / (1.0 - cars as f64 / jam).max(MIN_SPEED_RATIO)
+    }
+
+    /// Seconds needed to cross junction `node` from `from` to `to`, `None` if no lane allows it.
+    fn crossing_time(&self, node: usize, from: Direction, to: Direction) -> Option<f64> {
+        let layout = &self.nodes[node].layout;
+        let lane = *layout.lanes_for(from, to).first()?;
+        let (_, entry, exit, _) = layout.lanes(from, lane, to).ok()?;
+        Some(Path::new(turn(entry, exit)).length() / TURN_VELOCITY)
+    }
+
+    /// Junctions and turns on the quickest way from `entry` to `exit`, as `(node, from, to)`,
+    /// or `None` if `exit` cannot be reached. `load` holds the number of cars on every link,
+    /// links missing from it count as empty.
+    ///
+    /// Dijkstra over the ways of entering a junction, `(node, side)`. Networks are small enough
+    /// for the closest of them to be found by a linear scan.
+    pub fn turns(&self, entry: Port, exit: Port, load: &[usize]) -> Option<Vec<(usize, Direction, Direction)>> {
+        let sides = Direction::ALL.len();
+        let states = self.nodes.len() * sides;
+        let state = |node: usize, side: Direction| node * sides + side.index();
+        let unpack = |state: usize| (state / sides, Direction::ALL[state % sides]);
+
+        let mut time = vec![f64::INFINITY; states];
+        // Previous state, and the side it was left by
+        let mut previous: Vec<Option<(usize, Direction)>> = vec![None; states];
+        let mut done = vec![false; states];
+        // Quickest arrival found so far: time, last state, side it leaves by
+        let mut best: Option<(f64, usize, Direction)> = None;
+        time[state(entry.node, entry.side)] = 0.0;
+
+        while let Some(current) = (0..states).filter(|&s| !done[s] && time[s].is_finite())
+            .min_by(|&a, &b| time[a].total_cmp(&time[b])) {
+            if best.is_some_and(|(t, _, _)| t <= time[current]) {
+                break;
+            }
+            done[current] = true;
+            let (node, from) = unpack(current);
+            for to in self.nodes[node].layout.destinations(from) {
+                let Some(crossing) = self.crossing_time(node, from, to) else { continue };
+                let t = time[current] + crossing;
+                if node == exit.node && to == exit.side {
+                    if best.is_none_or(|(b, _, _)| t < b) {
+                        best = Some((t, current, to));
+                    }
+                    continue;
+                }
+                let Some(l) = self.links.iter().position(|l| l.from == node && l.side == to) else { continue };
+                let next = state(self.links[l].to, to.opposite());
+                let t = t + self.link_time(l, load.get(l).copied().unwrap_or(0));
+                if t < time[next] {
+                    time[next] = t;
+                    previous[next] = Some((current, to));
+                }
+            }
+        }
+
+        let (_, mut current, to) = best?;
+        let (node, from) = unpack(current);
+        let mut turns = vec![(node, from, to)];
+        while let Some((prev, to)) = previous[current] {
+            let (node, from) = unpack(prev);
+            turns.push((node, from, to));
+            current = prev;
+        }
+        turns.reverse();
+        Some(turns)
+    }
+
+    /// Exits reachable from `entry`.
+    pub fn destinations(&self, entry: Port) -> Vec<Port> {
+        self.exits.iter().copied().filter(|&x| self.turns(entry, x, &[]).is_some()).collect()
+    }
+
+    /// Quickest route from `entry` to `exit` given the cars on every link, see `turns`. The car
+    /// starts on a random lane allowed for its first movement, then keeps to its lane at the next
+    /// junctions when the movement is allowed from it, or moves to the closest lane that allows it.
+    pub fn route<R: Rng>(&self, entry: Port, exit: Port, load: &[usize], rng: &mut R) -> Result<Route, String> {
+        let turns = self.turns(entry, exit, load).ok_or_else(|| format!("{:?} cannot be reached from {:?}", exit, entry))?;
+        let mut hops = Vec::with_capacity(turns.len());
+        let mut arriving: Option<usize> = None;
+        for (node, from, to) in turns {
+            let layout = &self.nodes[node].layout;
+            let lanes = layout.lanes_for(from, to);
+            if lanes.is_empty() {
+                return Err(format!("no lane of `{}` goes from {:?} to {:?}", layout.name, from, to));
+            }
+            let lane = match arriving {
+                None => lanes[rng.gen_range(0..lanes.len())],
+                Some(current) => lanes.iter().copied().min_by_key(|l| l.abs_diff(current)).unwrap_or(lanes[0]),
+            };
+            let exit_lanes = layout.exit(to)?.lanes.len();
+            arriving = Some(lane.min(exit_lanes - 1));
+            hops.push(Hop { node, from, lane, to });
+        }
+        self.path(&hops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Layout;
+
+    /// Four junctions on the corners of a square, single lane roads.
+    const SQUARE: &str = r#"
+        name = "Square"
+        size = [900, 900]
+        lane_width = 50
+        lanes = 1
+        roads = [
+            { between = ["nw", "ne"] },
+            { between = ["nw", "sw"] },
+            { between = ["ne", "se"] },
+            { between = ["sw", "se"] },
+        ]
+        nodes = [
+            { name = "nw", center = [250, 250] },
+            { name = "ne", center = [650, 250] },
+            { name = "sw", center = [250, 650] },
+            { name = "se", center = [650, 650] },
+        ]
+    "#;
+
+    fn node(network: &Network, name: &str) -> usize {
+        network.nodes.iter().position(|n| n.name == name).unwrap()
+    }
+
+    fn link(network: &Network, from: &str, side: Direction) -> usize {
+        let from = node(network, from);
+        network.links.iter().position(|l| l.from == from && l.side == side).unwrap()
+    }
+
+    #[test]
+    fn straight_through_a_corridor() {
+        let network = Network::load("assets/corridor.toml").unwrap();
+        let entry = Port { node: 0, side: Direction::West };
+        let exit = Port { node: 3, side: Direction::East };
+        let turns = network.turns(entry, exit, &[]).unwrap();
+        let expected: Vec<_> = (0..4).map(|n| (n, Direction::West, Direction::East)).collect();
+        assert_eq!(turns, expected);
+    }
+
+    #[test]
+    fn one_junction_one_turn() {
+        let network = Network::load("assets/corridor.toml").unwrap();
+        let entry = Port { node: 1, side: Direction::North };
+        let exit = Port { node: 1, side: Direction::South };
+        assert_eq!(network.turns(entry, exit, &[]), Some(vec![(1, Direction::North, Direction::South)]));
+    }
+
+    #[test]
+    fn no_u_turn_back_out() {
+        let network = Network::single(Layout::load("assets/intersection.toml").unwrap());
+        let port = Port { node: 0, side: Direction::West };
+        assert_eq!(network.turns(port, port, &[]), None);
+        assert_eq!(network.destinations(port).len(), 3);
+    }
+
+    #[test]
+    fn around_the_block_to_leave_where_it_came_in() {
+        let network = Network::parse(SQUARE).unwrap();
+        let port = Port { node: node(&network, "nw"), side: Direction::West };
+        let turns = network.turns(port, port, &[]).unwrap();
+        assert_eq!(turns.len(), 5);
+        assert_eq!(turns.last(), Some(&(port.node, Direction::South, Direction::West)));
+    }
+
+    #[test]
+    fn congestion_diverts_around_the_busy_link() {
+        let network = Network::parse(SQUARE).unwrap();
+        let entry = Port { node: node(&network, "nw"), side: Direction::West };
+        let exit = Port { node: node(&network, "se"), side: Direction::East };
+        let via = |load: &[usize]| network.turns(entry, exit, load).unwrap()[1].0;
+
+        let mut load = vec![0; network.links.len()];
+        load[link(&network, "nw", Direction::East)] = 10;
+        assert_eq!(via(&load), node(&network, "sw"));
+
+        let mut load = vec![0; network.links.len()];
+        load[link(&network, "nw", Direction::South)] = 10;
+        assert_eq!(via(&load), node(&network, "ne"));
+    }
+
+    #[test]
+    fn loaded_links_take_longer() {
+        let network = Network::parse(SQUARE).unwrap();
+        let empty = network.link_time(0, 0);
+        assert!(network.link_time(0, 3) > empty);
+        // A jammed link keeps a finite cost
+        assert!(network.link_time(0, 1000).is_finite());
+        assert!((network.link_time(0, 1000) - empty / MIN_SPEED_RATIO).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::cars::Car;
+use crate::map::{Direction, Network};
+
+/// Cars allowed on one lane before spawns onto it are held back, as promised by the README.
+pub const DEFAULT_LANE_CAP: usize = 7;
+
+/// A lane of the network. Links are counted as the approach lanes of the junction they lead to.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum LaneId {
+    /// Approach lane `lane` of the approach guarded by light `light`, as in `Crossing`.
+    Approach { light: usize, lane: usize },
+    /// Lane `lane` of the road leaving the network by the `side` side of junction `node`.
+    Exit { node: usize, side: Direction, lane: usize },
+}
+
+/// Which cars are on which lane, recomputed after every step.
+#[derive(Debug,Clone)]
+pub struct Occupancy {
+    cap: usize,
+    lanes: Vec<LaneId>,
+    /// Ids of the cars on `lanes[i]`.
+    cars: Vec<Vec<u32>>,
+}
+
+impl Occupancy {
+    /// Every approach lane of `network` and every lane leaving it, empty, with at most `cap` cars per lane.
+    pub fn new(network: &Network, cap: usize) -> Self {
+        let mut lanes = Vec::new();
+        for node in &network.nodes {
+            for (i, approach) in node.layout.approaches.iter().enumerate() {
+                lanes.extend((0..approach.lanes.len()).map(|lane| LaneId::Approach { light: node.first_light + i, lane }));
+            }
+        }
+        for exit in &network.exits {
+            if let Ok(e) = network.nodes[exit.node].layout.exit(exit.side) {
+                lanes.extend((0..e.lanes.len()).map(|lane| LaneId::Exit { node: exit.node, side: exit.side, lane }));
+            }
+        }
+        Self { cap, cars: vec![Vec::new(); lanes.len()], lanes }
+    }
+
+    /// Lane `car` is driving on, `None` inside a junction.
+    fn lane_of(car: &Car, network: &Network) -> Option<LaneId> {
+        if let Some(c) = car.next_crossing() {
+            return Some(LaneId::Approach { light: c.light, lane: c.lane });
+        }
+        if car.crossing().is_some() {
+            return None;
+        }
+        let last = car.crossings().last()?;
+        let exit_lanes = network.nodes[last.node].layout.exit(last.to).ok()?.lanes.len();
+        Some(LaneId::Exit { node: last.node, side: last.to, lane: last.lane.min(exit_lanes - 1) })
+    }
+
+    pub fn update(&mut self, cars: &[Car], network: &Network) {
+        for lane in &mut self.cars {
+            lane.clear();
+        }
+        for car in cars {
+            let Some(lane) = Self::lane_of(car, network) else { continue };
+            if let Some(i) = self.lanes.iter().position(|&l| l == lane) {
+                self.cars[i].push(car.id());
+            }
+        }
+    }
+
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Every lane with the ids of the cars on it.
+    pub fn lanes(&self) -> impl Iterator<Item = (LaneId, &[u32])> {
+        self.lanes.iter().copied().zip(self.cars.iter().map(Vec::as_slice))
+    }
+
+    pub fn cars_on(&self, lane: LaneId) -> &[u32] {
+        self.lanes.iter().position(|&l| l == lane).map_or(&[], |i| self.cars[i].as_slice())
+    }
+
+    pub fn count(&self, lane: LaneId) -> usize {
+        self.cars_on(lane).len()
+    }
+
+    pub fn is_full(&self, lane: LaneId) -> bool {
+        self.count(lane) >= self.cap
+    }
+
+    /// Cars on all the lanes of the approach guarded by light `light`.
+    pub fn approach(&self, light: usize) -> usize {
+        self.lanes()
+            .filter(|(l, _)| matches!(l, LaneId::Approach { light: a, .. } if *a == light))
+            .map(|(_, cars)| cars.len())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::map::{self, Layout, Port};
+    use super::*;
+
+    fn car(network: &Network, from: Direction, to: Direction, id: u32) -> Car {
+        let mut rng = StdRng::seed_from_u64(0);
+        let route = network.route(Port { node: 0, side: from }, Port { node: 0, side: to }, &[], &mut rng).unwrap();
+        let mut car = map::spawn_car(route, 32, 45).unwrap();
+        car.set_id(id);
+        car
+    }
+
+    #[test]
+    fn cars_are_counted_on_their_approach_lane() {
+        let network = Network::single(Layout::load(map::DEFAULT_LAYOUT).unwrap());
+        let mut occupancy = Occupancy::new(&network, 2);
+        let north = LaneId::Approach { light: 0, lane: 0 };
+        let cars = [
+            car(&network, Direction::North, Direction::South, 0),
+            car(&network, Direction::North, Direction::East, 1),
+            car(&network, Direction::West, Direction::East, 2),
+        ];
+        occupancy.update(&cars[..1], &network);
+        assert_eq!(occupancy.cars_on(north), &[0]);
+        assert!(!occupancy.is_full(north));
+        occupancy.update(&cars, &network);
+        assert_eq!(occupancy.cars_on(north), &[0, 1]);
+        assert!(occupancy.is_full(north));
+        assert_eq!(occupancy.approach(0), 2);
+        assert_eq!(occupancy.approach(3), 1);
+        assert_eq!(occupancy.lanes().map(|(_, cars)| cars.len()).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn cars_move_to_their_exit_lane_past_the_junction() {
+        let network = Network::single(Layout::load(map::DEFAULT_LAYOUT).unwrap());
+        let mut occupancy = Occupancy::new(&network, DEFAULT_LANE_CAP);
+        let mut cars = [car(&network, Direction::North, Direction::South, 0)];
+        let mut lanes = Vec::new();
+        for _ in 0..600 {
+            cars[0].update(1.0 / 60.0, &[]);
+            occupancy.update(&cars, &network);
+            let lane = occupancy.lanes().find(|(_, cars)| !cars.is_empty()).map(|(lane, _)| lane);
+            if lanes.last() != Some(&lane) {
+                lanes.push(lane);
+            }
+        }
+        let exit = LaneId::Exit { node: 0, side: Direction::South, lane: 0 };
+        assert_eq!(lanes, vec![Some(LaneId::Approach { light: 0, lane: 0 }), None, Some(exit)]);
+    }
+}
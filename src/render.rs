@@ -0,0 +1,272 @@
+use sdl2::{pixels::Color, rect::{Point, Rect}, render::{Canvas, Texture}, video::Window};
+
+use smart_road_remake::cars::{Car, DisplayType, Rgb, UpdateState};
+use crate::entities::Entity;
+use smart_road_remake::geometry::{self, Obb, Vec2};
+use smart_road_remake::map::{Movement, Network, TrafficLight, TrafficLightState};
+use smart_road_remake::occupancy::LaneId;
+use smart_road_remake::simulation::Simulation;
+
+fn point(p: geometry::Point) -> Point {
+    Point::new(p.x, p.y)
+}
+
+fn rect(r: geometry::Rect) -> Rect {
+    Rect::new(r.x, r.y, r.w, r.h)
+}
+
+fn color(c: Rgb) -> Color {
+    Color::RGB(c.0, c.1, c.2)
+}
+
+// Colors
+const GRASS_COLOR: Color = Color::RGB(34, 139, 34);    // Forest green
+const ROAD_COLOR: Color = Color::RGB(50, 50, 50);      // Dark gray for road
+const LANE_MARKER_COLOR: Color = Color::RGB(255, 255, 255); // White for lane markers
+
+const QUEUE_COLOR: Color = Color::RGB(255, 165, 0);    // Orange for cars waiting to enter
+const AMBER: Color = Color::RGB(255, 200, 0);          // Yellow signal heads
+const LOOP_COLOR: Color = Color::RGB(90, 90, 120);     // Detector loops
+const LOOP_ACTIVE_COLOR: Color = Color::RGB(120, 200, 255);
+const BOOKED_COLOR: Color = Color::RGB(200, 120, 255);  // Conflict zone cells held for a car right now
+
+const MARKER_WIDTH: f64 = 4.0;
+/// Size of, and spacing between, the markers of waiting cars.
+const QUEUE_MARKER: u32 = 6;
+const QUEUE_SPACING: f64 = 10.0;
+/// Waiting cars drawn per entry lane, at most.
+const QUEUE_SHOWN: usize = 12;
+/// Size of, and spacing between, the signal heads of an approach.
+const HEAD_SIZE: u32 = 10;
+const HEAD_SPACING: f64 = 12.0;
+const DASH_LENGTH: f64 = 30.0;
+const DASH_GAP: f64 = 30.0;
+
+/// Draw one frame of the simulation. The simulation is only read, cars are drawn
+/// `alpha` of the way between the last two simulation steps.
+pub fn draw(canvas: &mut Canvas<Window>, atlas: &Texture, sim: &Simulation, alpha: f64, debug: bool) -> Result<(), String> {
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+
+    load_map(canvas, sim.network())?;
+    draw_detectors(canvas, sim)?;
+
+    for c in sim.cars() {
+        if debug {
+            let (probe,leader) = c.get_detections();
+            canvas.set_draw_color(if *c.state() == UpdateState::Waiting { Color::RED } else { Color::GRAY });
+            draw_obb(canvas, &probe)?;
+            if let Some(leader) = leader {
+                canvas.set_draw_color(Color::YELLOW);
+                canvas.draw_line(point(c.get_hitbox().center()), point(leader))?;
+            }
+        }
+        c.display(canvas, atlas, alpha, debug).map_err(|e| e.to_string())?;
+    }
+
+    draw_occupancy(canvas, sim)?;
+    draw_queues(canvas, sim)?;
+
+    canvas.set_draw_color(BOOKED_COLOR);
+    for (controller, grid) in sim.controllers().iter().zip(sim.grids()) {
+        for cell in controller.held_cells(sim.time()) {
+            canvas.draw_rect(rect(grid.rect(cell)))?;
+        }
+    }
+    for light in sim.controllers().iter().flat_map(|c| c.lights()) {
+        draw_heads(canvas, light)?;
+    }
+
+    canvas.present();
+    Ok(())
+}
+
+/// Draw the detector loops, lit while a car is over them.
+fn draw_detectors(canvas: &mut Canvas<Window>, sim: &Simulation) -> Result<(), String> {
+    let width = sim.network().nodes.first().map_or(0.0, |n| n.layout.lane_width as f64 * 0.5);
+    for d in sim.detectors().iter() {
+        canvas.set_draw_color(if d.occupied() { LOOP_ACTIVE_COLOR } else { LOOP_COLOR });
+        fill_band(canvas, d.ends.0, d.ends.1, width)?;
+    }
+    Ok(())
+}
+
+/// Draw one head per movement of the approach of `light`, left turn on the driver's left.
+/// Protected greens are filled, permissive ones only outlined.
+fn draw_heads(canvas: &mut Canvas<Window>, light: &TrafficLight) -> Result<(), String> {
+    let right = (-light.from.outward()).perpendicular();
+    for m in Movement::ALL {
+        let center = Vec2::from(light.position) + right * ((m.index() as f64 - 1.0) * HEAD_SPACING);
+        let rect = Rect::from_center(point(center.to_point()), HEAD_SIZE, HEAD_SIZE);
+        let state = light.state(m);
+        canvas.set_draw_color(match state {
+            TrafficLightState::Green | TrafficLightState::Permissive => Color::GREEN,
+            TrafficLightState::Yellow => AMBER,
+            TrafficLightState::Red => Color::RED,
+        });
+        if state == TrafficLightState::Permissive {
+            canvas.draw_rect(rect)?;
+        } else {
+            canvas.fill_rect(rect)?;
+        }
+    }
+    Ok(())
+}
+
+/// Fill the band `width` pixels wide centred on the segment `a`-`b`.
+fn fill_band(canvas: &mut Canvas<Window>, a: Vec2, b: Vec2, width: f64) -> Result<(), String> {
+    let d = b - a;
+    if d.x.abs() < f64::EPSILON || d.y.abs() < f64::EPSILON {
+        return canvas.fill_rect(rect(Obb::new(a.lerp(b, 0.5), d.angle(), d.length(), width).aabb()));
+    }
+    let across = d.normalized().perpendicular();
+    for i in 0..=width.ceil() as i32 {
+        let offset = across * (i as f64 - width / 2.0);
+        canvas.draw_line(point((a + offset).to_point()), point((b + offset).to_point()))?;
+    }
+    Ok(())
+}
+
+/// Roads, lane markings and stop lines of every junction of the network.
+fn load_map(canvas: &mut Canvas<Window>, network: &Network) -> Result<(), String> {
+    // Set background color (grass)
+    canvas.set_draw_color(GRASS_COLOR);
+    canvas.clear();
+
+    // Lanes with the middle of their junction. An exit lane feeding another junction
+    // is the approach lane of that junction, it is only drawn once.
+    let mut lanes = Vec::new();
+    for (n, node) in network.nodes.iter().enumerate() {
+        let layout = &node.layout;
+        let middle = Vec2::from(layout.conflict_zone.center());
+        let width = layout.lane_width as f64;
+        lanes.extend(layout.approaches.iter().flat_map(|a| a.lanes.iter().map(|l| l.lane))
+            .chain(layout.exits.iter().filter(|e| network.link(n, e.to).is_none()).flat_map(|e| e.lanes.iter().copied()))
+            .map(|lane| (lane, middle, width)));
+    }
+
+    // Pave every lane up to the middle of its junction so the corners get covered too
+    canvas.set_draw_color(ROAD_COLOR);
+    for &(lane, middle, width) in &lanes {
+        let along = (middle - lane.start).dot(lane.direction());
+        fill_band(canvas, lane.start, lane.start + lane.direction() * along, width)?;
+    }
+    for node in &network.nodes {
+        canvas.fill_rect(rect(node.layout.conflict_zone))?;
+    }
+
+    // Dashes on the left edge of every lane, outside of the junctions
+    canvas.set_draw_color(LANE_MARKER_COLOR);
+    for &(lane, _, width) in &lanes {
+        let dir = lane.direction();
+        let edge = -dir.perpendicular() * (width / 2.0);
+        let length = (lane.end - lane.start).length();
+        let mut d = 0.0;
+        while d < length {
+            let a = lane.start + dir * d + edge;
+            let b = lane.start + dir * (d + DASH_LENGTH).min(length) + edge;
+            fill_band(canvas, a, b, MARKER_WIDTH)?;
+            d += DASH_LENGTH + DASH_GAP;
+        }
+    }
+
+    // Stop lines across the end of the approaches
+    for layout in network.nodes.iter().map(|n| &n.layout) {
+        for lane in layout.approaches.iter().flat_map(|a| &a.lanes) {
+            let lane = lane.lane;
+            let across = lane.direction().perpendicular() * (layout.lane_width as f64 / 2.0);
+            fill_band(canvas, lane.end - across, lane.end + across, MARKER_WIDTH)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Tint every stop line from white to red as its lane fills up to the cap, and sum it up in the window title.
+fn draw_occupancy(canvas: &mut Canvas<Window>, sim: &Simulation) -> Result<(), String> {
+    let network = sim.network();
+    let occupancy = sim.occupancy();
+    let mut full = 0;
+    let mut busiest = 0;
+    for (lane, cars) in occupancy.lanes() {
+        busiest = busiest.max(cars.len());
+        if cars.len() >= occupancy.cap() {
+            full += 1;
+        }
+        let LaneId::Approach { light, lane } = lane else { continue };
+        let Some((node, approach)) = network.approach_by_light(light) else { continue };
+        if cars.is_empty() {
+            continue;
+        }
+        let fill = (cars.len() as f64 / occupancy.cap() as f64).min(1.0);
+        let fade = (255.0 * (1.0 - fill)) as u8;
+        canvas.set_draw_color(Color::RGB(255, fade, fade));
+        let lane = approach.lanes[lane].lane;
+        let across = lane.direction().perpendicular() * (network.nodes[node].layout.lane_width as f64 / 2.0);
+        fill_band(canvas, lane.end - across, lane.end + across, MARKER_WIDTH)?;
+    }
+
+    let title = format!("{} | {:.0} s | {} cars | {} waiting | busiest lane {}/{} | {} full lanes",
+        network.name, sim.time(), sim.cars().len(), sim.unserved(), busiest, occupancy.cap(), full);
+    canvas.window_mut().set_title(&title).map_err(|e| e.to_string())
+}
+
+/// One marker per car waiting to enter, in a row from where its entry lane comes onto the screen.
+fn draw_queues(canvas: &mut Canvas<Window>, sim: &Simulation) -> Result<(), String> {
+    let network = sim.network();
+    let (w, h) = network.size;
+    let margin = QUEUE_MARKER as f64;
+    canvas.set_draw_color(QUEUE_COLOR);
+    for queue in sim.queues() {
+        let Ok((_, approach)) = network.nodes[queue.port.node].layout.approach(queue.port.side) else { continue };
+        let lane = approach.lanes[queue.lane].lane;
+        let edge = Vec2::new(lane.start.x.clamp(margin, w as f64 - margin), lane.start.y.clamp(margin, h as f64 - margin));
+        for i in 0..queue.len().min(QUEUE_SHOWN) {
+            let at = edge + lane.direction() * (i as f64 * QUEUE_SPACING);
+            canvas.fill_rect(Rect::from_center(point(at.to_point()), QUEUE_MARKER, QUEUE_MARKER))?;
+        }
+    }
+    Ok(())
+}
+
+/// Outline of an oriented box.
+fn draw_obb(canvas: &mut Canvas<Window>, obb: &Obb) -> Result<(), String> {
+    let c = obb.corners();
+    let points: Vec<_> = c.iter().chain(&c[..1]).map(|p| point(p.to_point())).collect();
+    canvas.draw_lines(points.as_slice())
+}
+
+impl Entity for Car {
+    fn display(&self, canvas: &mut Canvas<Window>, atlas: &Texture, alpha: f64, debug: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let center = point(self.interpolated_center(alpha));
+        match self.sprite() {
+            DisplayType::Color(c) => {
+                let hb = self.get_hitbox();
+                canvas.set_draw_color(color(c));
+                canvas.fill_rect(Rect::from_center(center, hb.w, hb.h))?;
+            },
+            DisplayType::Texture(region) => {
+                if debug {
+                    canvas.set_draw_color(Color::GREEN);
+                    draw_obb(canvas, &self.obb())?;
+                }
+                let (w,l) = self.w_l();
+                canvas.copy_ex(
+                    atlas,
+                    Some(rect(region)),
+                    Some(Rect::from_center(center,w,l)),
+                    // The sprites face up the screen, which is a heading of -90°
+                    self.heading().to_degrees() + 90.0,
+                    None,
+                    false,
+                    false
+                )?;
+            },
+        }
+        Ok(())
+    }
+
+    fn get_hitbox(&self) -> geometry::Rect {
+        self.hitbox()
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use crate::cars::{Car, BASE_VELOCITY, TURN_VELOCITY};
+use crate::intersection::{distance_to_line, stopped_at_line, Context, IntersectionController, APPROACH_DISTANCE};
+use crate::map::{ConflictGrid, Crossing};
+
+/// Step between the arrival times tried for a reservation, and how far ahead they go, in seconds.
+const SLOT_STEP_S: f64 = 0.1;
+const HORIZON_S: f64 = 60.0;
+/// Time kept free before and after each car in a cell, in seconds.
+const MARGIN_S: f64 = 0.25;
+/// Time between two cars of the same lane reaching the stop line, in seconds.
+const FOLLOW_S: f64 = 0.5;
+/// How long after its slot a car that has not entered the junction keeps it, in seconds.
+const LATE_S: f64 = 0.5;
+/// Distance before the stop line a held car may start from, in pixels.
+const HOLD_GAP: f64 = 30.0;
+
+/// Arrival at the stop line granted to a car.
+#[derive(Debug,Clone,Copy)]
+struct Grant {
+    light: usize,
+    lane: usize,
+    /// Distance driven along the car's path when its front reaches the stop line.
+    start: f64,
+    /// Time the car may reach the stop line, in seconds.
+    at: f64,
+}
+
+/// One car's hold on a cell: the time it may be over it, and the distance past the stop line
+/// at which it leaves it.
+#[derive(Debug,Clone,Copy)]
+struct Booking {
+    car: u32,
+    from: f64,
+    to: f64,
+    last: f64,
+}
+
+/// Autonomous intersection management of one junction: instead of lights, cars book the cells
+/// of its conflict grid they will drive over, for the time they will be there, and wait at the
+/// stop line until their slot comes.
+#[derive(Debug,Clone)]
+pub struct ReservationManager {
+    /// Bookings of each cell of the grid.
+    bookings: Vec<Vec<Booking>>,
+    grants: HashMap<u32, Grant>,
+}
+
+/// Seconds needed to drive `distance` from rest, accelerating at `accel` up to `speed`.
+fn time_from_rest(distance: f64, accel: f64, speed: f64) -> f64 {
+    let ramp = speed * speed / (2.0 * accel);
+    if distance < ramp {
+        (2.0 * distance / accel).sqrt()
+    } else {
+        speed / accel + (distance - ramp) / speed
+    }
+}
+
+impl ReservationManager {
+    /// Empty book for a junction whose conflict grid is `grid`.
+    pub fn new(grid: &ConflictGrid) -> Self {
+        Self {
+            bookings: vec![Vec::new(); grid.cells()],
+            grants: HashMap::new(),
+        }
+    }
+
+    /// Drop the reservation of car `id` and free its cells.
+    fn cancel(&mut self, id: u32) {
+        if self.grants.remove(&id).is_some() {
+            for cells in &mut self.bookings {
+                cells.retain(|b| b.car != id);
+            }
+        }
+    }
+
+    /// Free the cells `car`, inside the junction, has driven past and keep the ones
+    /// ahead booked until at least `MARGIN_S` from now. Returns the other cars booked on them
+    /// in the meantime.
+    fn hold_cells(&mut self, car: &Car, grant: &Grant, time: f64) -> Vec<u32> {
+        let x = car.travelled() - grant.start;
+        let mut clashes = Vec::new();
+        for cell in &mut self.bookings {
+            cell.retain(|b| b.car != car.id() || b.last >= x);
+            let Some(own) = cell.iter_mut().find(|b| b.car == car.id()) else { continue };
+            own.to = own.to.max(time + MARGIN_S);
+            let (from, to) = (own.from, own.to);
+            clashes.extend(cell.iter().filter(|b| b.car != car.id() && b.from <= to && from <= b.to).map(|b| b.car));
+        }
+        clashes
+    }
+
+    /// Book the earliest arrival of `car` at its stop line at `crossing` for which every cell
+    /// its route sweeps is free. The car may arrive at full speed or start from rest just behind
+    /// the line, so each cell is booked from the earliest to the latest it could be there.
+    fn request(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) {
+        let first = ctx.network.nodes[crossing.node].first_light;
+        let Some(route) = ctx.grid.route(crossing.light - first, crossing.lane, crossing.to) else { return };
+        let cells = &ctx.grid.routes()[route].cells;
+        let (distance, time) = (distance_to_line(car, crossing), ctx.time);
+        let accel = car.dynamics().max_accel;
+        let windows: Vec<(usize, f64, f64, f64)> = cells.iter()
+            .map(|c| (
+                c.cell,
+                c.enter / BASE_VELOCITY - MARGIN_S,
+                time_from_rest(c.leave + HOLD_GAP, accel, TURN_VELOCITY) + MARGIN_S,
+                c.leave,
+            ))
+            .collect();
+
+        let behind = self.grants.values()
+            .filter(|g| g.light == crossing.light && g.lane == crossing.lane)
+            .map(|g| g.at + FOLLOW_S)
+            .fold(f64::MIN, f64::max);
+        let cells = &self.bookings;
+        let mut at = (time + distance.max(0.0) / BASE_VELOCITY).max(behind);
+        while at < time + HORIZON_S {
+            let free = windows.iter().all(|&(cell, from, to, _)| {
+                cells[cell].iter().all(|b| at + to < b.from || b.to < at + from)
+            });
+            if free {
+                for &(cell, from, to, last) in &windows {
+                    self.bookings[cell].push(Booking { car: car.id(), from: at + from, to: at + to, last });
+                }
+                let start = crossing.stop - car.w_l().1 as f64 / 2.0;
+                self.grants.insert(car.id(), Grant { light: crossing.light, lane: crossing.lane, start, at });
+                return;
+            }
+            at += SLOT_STEP_S;
+        }
+    }
+}
+
+impl IntersectionController for ReservationManager {
+    fn on_approach(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) {
+        self.request(car, crossing, ctx);
+    }
+
+    /// A car goes once it can reach its line no earlier than its slot. A car too late for its
+    /// slot, or whose slot was taken back, books again.
+    fn may_enter(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        let distance = distance_to_line(car, crossing);
+        if self.grants.get(&car.id()).is_some_and(|g| ctx.time > g.at + LATE_S) {
+            self.cancel(car.id());
+        }
+        if !self.grants.contains_key(&car.id()) && distance < APPROACH_DISTANCE {
+            self.request(car, crossing, ctx);
+        }
+        self.grants.get(&car.id()).is_some_and(|g| ctx.time >= g.at - distance.max(0.0) / BASE_VELOCITY)
+    }
+
+    /// Cars booked on the cells of `car`'s route before it, all the cars booked on them if it
+    /// has no slot. Not those queued behind it in its lane, which go after it whatever they hold.
+    fn waiting_for(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> Vec<u32> {
+        let Some(route) = ctx.route(crossing) else { return Vec::new() };
+        let distance = distance_to_line(car, crossing);
+        let behind = |id: u32| ctx.cars.iter().any(|c| {
+            c.id() == id && c.next_crossing().is_some_and(|n| n.node == crossing.node && n.lane == crossing.lane
+                && n.light == crossing.light && distance_to_line(c, &n) > distance)
+        });
+        let mut ids = Vec::new();
+        for span in &ctx.grid.routes()[route].cells {
+            let cell = &self.bookings[span.cell];
+            let own = cell.iter().find(|b| b.car == car.id()).map_or(f64::MAX, |b| b.from);
+            for b in cell.iter().filter(|b| b.car != car.id() && b.from < own && !behind(b.car)) {
+                if !ids.contains(&b.car) {
+                    ids.push(b.car);
+                }
+            }
+        }
+        ids
+    }
+
+    /// Take back the slots of the cars not in the junction yet and book `car` first, the others
+    /// booking again after it.
+    fn give_priority(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        if !stopped_at_line(car, crossing) {
+            return false;
+        }
+        let waiting: Vec<u32> = self.grants.keys().copied()
+            .filter(|&id| ctx.cars.iter().any(|c| c.id() == id && !c.has_entered_intersection()))
+            .collect();
+        for id in waiting {
+            self.cancel(id);
+        }
+        self.request(car, crossing, ctx);
+        self.grants.contains_key(&car.id())
+    }
+
+    /// Drop the bookings past, and keep the cells ahead of cars slower through the junction
+    /// than booked. Cars not in yet whose slot now clashes with them book again.
+    fn tick(&mut self, _dt: f64, ctx: &Context) {
+        for cells in &mut self.bookings {
+            cells.retain(|b| b.to >= ctx.time);
+        }
+        for car in ctx.cars.iter().filter(|c| c.has_entered_intersection()) {
+            if let Some(grant) = self.grants.get(&car.id()).copied() {
+                for id in self.hold_cells(car, &grant, ctx.time) {
+                    if ctx.cars.iter().any(|c| c.id() == id && !c.has_entered_intersection()) {
+                        self.cancel(id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn on_exit(&mut self, car: u32, _ctx: &Context) {
+        self.cancel(car);
+    }
+
+    fn held_cells(&self, time: f64) -> Vec<usize> {
+        self.bookings.iter().enumerate()
+            .filter(|(_, b)| b.iter().any(|b| b.from <= time && time <= b.to))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intersection::testing::{drive, Junction};
+    use crate::map::Direction;
+    use super::*;
+
+    fn booked(manager: &ReservationManager, car: u32) -> Vec<usize> {
+        (0..manager.bookings.len()).filter(|&c| manager.bookings[c].iter().any(|b| b.car == car)).collect()
+    }
+
+    fn announce(manager: &mut ReservationManager, junction: &Junction, cars: &[Car]) {
+        let ctx = junction.context(0.0, cars);
+        for car in cars {
+            manager.on_approach(car, &car.next_crossing().unwrap(), &ctx);
+        }
+    }
+
+    #[test]
+    fn lone_car_is_granted_its_route_at_once() {
+        let junction = Junction::new();
+        let mut manager = ReservationManager::new(&junction.grid);
+        let cars = [junction.car(0, Direction::North, Direction::South)];
+        announce(&mut manager, &junction, &cars);
+        let crossing = cars[0].next_crossing().unwrap();
+        let route = junction.context(0.0, &cars).route(&crossing).unwrap();
+        let mut cells: Vec<usize> = junction.grid.routes()[route].cells.iter().map(|s| s.cell).collect();
+        cells.sort_unstable();
+        cells.dedup();
+        assert_eq!(booked(&manager, 0), cells);
+        assert!(manager.may_enter(&cars[0], &crossing, &junction.context(0.0, &cars)));
+    }
+
+    #[test]
+    fn crossing_cars_get_slots_apart() {
+        let junction = Junction::new();
+        let mut manager = ReservationManager::new(&junction.grid);
+        let cars = [
+            junction.car(0, Direction::North, Direction::South),
+            junction.car(1, Direction::South, Direction::North),
+            junction.car(2, Direction::East, Direction::West),
+        ];
+        announce(&mut manager, &junction, &cars);
+        let ctx = junction.context(0.0, &cars);
+        let go: Vec<bool> = cars.iter().map(|c| manager.may_enter(c, &c.next_crossing().unwrap(), &ctx)).collect();
+        // The straight ahead from the south does not cross the one from the north
+        assert_eq!(go, vec![true, true, false]);
+        assert!(manager.grants[&2].at > manager.grants[&0].at);
+        for cell in booked(&manager, 2) {
+            let mut times: Vec<&Booking> = manager.bookings[cell].iter().collect();
+            times.sort_by(|a, b| a.from.total_cmp(&b.from));
+            assert!(times.windows(2).all(|w| w[0].to < w[1].from));
+        }
+    }
+
+    #[test]
+    fn cells_are_freed_behind_the_car_and_on_exit() {
+        let junction = Junction::new();
+        let mut manager = ReservationManager::new(&junction.grid);
+        let mut cars = [junction.car(0, Direction::North, Direction::South)];
+        announce(&mut manager, &junction, &cars);
+        let before = booked(&manager, 0).len();
+        let mut time = 0.0;
+        while !cars[0].has_entered_intersection() || booked(&manager, 0).len() == before {
+            assert!(cars[0].crossing().is_some(), "no cell was freed before the car left the junction");
+            let last = booked(&manager, 0).len();
+            drive(&mut cars, 1.0 / 60.0);
+            time += 1.0 / 60.0;
+            manager.tick(1.0 / 60.0, &junction.context(time, &cars));
+            assert!(booked(&manager, 0).len() <= last);
+        }
+        assert!(cars[0].has_entered_intersection());
+        manager.on_exit(0, &junction.context(time, &cars));
+        assert!(booked(&manager, 0).is_empty());
+        assert!(manager.held_cells(time).is_empty());
+    }
+}
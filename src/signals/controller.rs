@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+
+use crate::cars::Car;
+use crate::intersection::{can_stop, stopped_at_line, Context, IntersectionController, ACCEPTED_GAP_S};
+use crate::map::{Crossing, Direction, Movement, Network, TrafficLight, TrafficLightState};
+use crate::occupancy::{LaneId, Occupancy};
+use super::{Actuation, Control, Detectors, LoopKind, Phase, SignalPlan};
+
+/// What one movement adds to the pressure of a phase: the cars queued for it, minus the mean
+/// lane queue of the road it feeds.
+#[derive(Debug,Clone)]
+struct Pressure {
+    /// Approach lanes carrying the movement, weighted by the share of their cars it gets,
+    /// lanes carrying several movements being shared evenly.
+    upstream: Vec<(LaneId, f64)>,
+    /// Light of the approach the movement feeds at the next junction and its number of lanes,
+    /// `None` when it leaves the network.
+    downstream: Option<(usize, usize)>,
+}
+
+impl Pressure {
+    fn new(node: usize, network: &Network, from: Direction, movement: Movement) -> Self {
+        let n = &network.nodes[node];
+        let upstream = n.layout.approach(from).map(|(index, approach)| {
+            approach.lanes.iter().enumerate()
+                .filter(|(_, l)| l.movements.contains(&movement))
+                .map(|(lane, l)| (LaneId::Approach { light: n.first_light + index, lane }, 1.0 / l.movements.len() as f64))
+                .collect()
+        }).unwrap_or_default();
+        let to = movement.exit(from);
+        let downstream = network.link(node, to)
+            .and_then(|link| network.light(link.to, to.opposite()).map(|light| (light, link.lanes)));
+        Self { upstream, downstream }
+    }
+
+    fn value(&self, occupancy: &Occupancy) -> f64 {
+        let queued: f64 = self.upstream.iter().map(|&(lane, share)| occupancy.count(lane) as f64 * share).sum();
+        let fed = self.downstream.map_or(0.0, |(light, lanes)| occupancy.approach(light) as f64 / lanes as f64);
+        queued - fed
+    }
+}
+
+/// Drives all the signal heads of one junction, running the phases of a plan in turn, and
+/// lets cars in by what the head of their movement shows.
+#[derive(Debug,Clone)]
+pub struct SignalController {
+    /// Index of the junction in the network.
+    node: usize,
+    /// Index of the first light of the junction among all the lights of the network.
+    first_light: usize,
+    /// One per approach, in the order of the layout.
+    lights: Vec<TrafficLight>,
+    phases: Vec<Phase>,
+    control: Control,
+    actuation: Actuation,
+    right_on_red: bool,
+    /// Terms of the pressure of each phase, for adaptive control.
+    pressures: Vec<Vec<Pressure>>,
+
+    /// Phase showing green, or clearing once its green has ended.
+    phase: usize,
+    /// Seconds since the green of `phase` started.
+    elapsed: f64,
+    /// When the green of `phase` ended and the phase chosen to follow it, during the clearance.
+    ending: Option<(f64, usize)>,
+    /// Seconds since a car last drove over an advance loop of `phase` or stood on its stop bar.
+    since_call: f64,
+    /// Phases started so far, telling the clearance after one green from the next.
+    started: u64,
+    /// Whether cars that met an amber light, or stopped to turn right on red, go through, with
+    /// the phase it was decided in. Kept until the next phase starts, so a car too close to
+    /// stop carries on when the light turns red, and meets the next amber afresh.
+    committed: HashMap<u32, (u64, bool)>,
+    /// Cars giving way that the others give way to instead to break a deadlock, the last given
+    /// it first.
+    priority: Vec<u32>,
+}
+
+impl SignalController {
+    /// Controller of junction `node` running `plan`, started as far into the cycle as the
+    /// junction's offset asks.
+    pub fn new(node: usize, network: &Network, plan: &SignalPlan) -> Self {
+        let n = &network.nodes[node];
+        let mut controller = Self {
+            node,
+            first_light: n.first_light,
+            lights: TrafficLight::reposition_for_entry_lanes(&n.layout),
+            phases: plan.phases.clone(),
+            control: plan.control,
+            actuation: plan.actuation,
+            right_on_red: plan.right_on_red,
+            pressures: plan.phases.iter()
+                .map(|p| p.movements.iter().map(|&(from, m)| Pressure::new(node, network, from, m)).collect())
+                .collect(),
+            phase: 0,
+            elapsed: 0.0,
+            ending: None,
+            since_call: 0.0,
+            started: 0,
+            committed: HashMap::new(),
+            priority: Vec::new(),
+        };
+        // Start the cycle shifted by the junction's offset
+        let offset = plan.offsets.get(&n.name).copied().unwrap_or(0.0);
+        let mut clock = (-offset).rem_euclid(plan.cycle());
+        while controller.phase + 1 < controller.phases.len() && clock >= controller.phases[controller.phase].length() {
+            clock -= controller.phases[controller.phase].length();
+            controller.phase += 1;
+        }
+        controller.elapsed = clock;
+        let split = controller.phases[controller.phase].split;
+        if clock >= split {
+            controller.ending = Some((split, (controller.phase + 1) % controller.phases.len()));
+        }
+        controller.apply();
+        controller
+    }
+
+    /// Whether a loop of the junction reports a car on a lane released by `phase`.
+    fn called(&self, phase: &Phase, detectors: &Detectors) -> bool {
+        detectors.at(self.node)
+            .filter(|d| d.actuated())
+            .any(|d| phase.movements.iter().any(|&(from, m)| d.serves(from, m)))
+    }
+
+    /// Whether the loops of the current phase ask for more green: a car arriving over an
+    /// advance loop, or cars still on a stop bar.
+    fn extended(&self, detectors: &Detectors) -> bool {
+        let phase = &self.phases[self.phase];
+        detectors.at(self.node)
+            .filter(|d| match d.kind {
+                LoopKind::Advance => d.pulse(),
+                LoopKind::StopBar => d.occupied(),
+            })
+            .any(|d| phase.movements.iter().any(|&(from, m)| d.serves(from, m)))
+    }
+
+    /// Pressure of phase `phase`: the sum of the pressures of the movements it releases.
+    fn pressure(&self, phase: usize, occupancy: &Occupancy) -> f64 {
+        self.pressures[phase].iter().map(|p| p.value(occupancy)).sum()
+    }
+
+    /// When the green of the current phase ends and which phase follows, if it ends now.
+    /// An actuated phase ends on gap-out or max-out, once it has had its minimum green, and
+    /// only if another phase has a car waiting: the green rests otherwise. An adaptive phase
+    /// ends once it has had its minimum green and another phase has a higher pressure, or
+    /// on max-out if another phase has cars queued.
+    fn end_of_green(&self, calls: &[bool], occupancy: &Occupancy) -> Option<(f64, usize)> {
+        let phase = &self.phases[self.phase];
+        let n = self.phases.len();
+        match self.control {
+            Control::Fixed => (self.elapsed >= phase.split).then_some((phase.split, (self.phase + 1) % n)),
+            Control::Actuated => {
+                let gap_out = self.since_call >= self.actuation.passage;
+                let max_out = self.elapsed >= phase.split;
+                if self.elapsed < self.actuation.min_green || !(gap_out || max_out) {
+                    return None;
+                }
+                (1..n).map(|k| (self.phase + k) % n)
+                    .find(|&p| calls[p])
+                    .map(|p| (self.elapsed, p))
+            },
+            Control::Adaptive => {
+                if self.elapsed < self.actuation.min_green {
+                    return None;
+                }
+                let current = self.pressure(self.phase, occupancy);
+                let max_out = self.elapsed >= phase.split;
+                (1..n).map(|k| (self.phase + k) % n)
+                    .map(|p| (p, self.pressure(p, occupancy)))
+                    .filter(|&(p, pressure)| pressure > current || (max_out && calls[p]))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(p, _)| (self.elapsed, p))
+            },
+        }
+    }
+
+    /// Set every head of the junction to what the current phase shows. Movements the next phase
+    /// keeps keep their state through the clearance, the others go amber then red.
+    fn apply(&mut self) {
+        let phase = &self.phases[self.phase];
+        let clearing = self.ending.map(|(end, next)| (self.elapsed - end, &self.phases[next]));
+        for light in &mut self.lights {
+            light.right_on_red = self.right_on_red;
+            for m in Movement::ALL {
+                let state = phase.indication(light.from, m);
+                let state = match clearing.map(|(t, next)| (t, next.indication(light.from, m))) {
+                    _ if state == TrafficLightState::Red => state,
+                    Some((t, TrafficLightState::Red)) if t >= phase.yellow => TrafficLightState::Red,
+                    Some((_, TrafficLightState::Red)) => TrafficLightState::Yellow,
+                    _ => state,
+                };
+                light.set(m, state);
+            }
+        }
+    }
+
+    /// Cars on a route of the conflict grid crossing `car`'s at `crossing` that are not past
+    /// the cells it shares with it, and in the junction or released and less than
+    /// `ACCEPTED_GAP_S` from the first of these cells. A car given
+    /// priority only waits for the cars in the junction and the ones given it since, and the
+    /// others wait for it.
+    fn yields_to<'a>(&'a self, car: &'a Car, crossing: &'a Crossing, ctx: &'a Context) -> impl Iterator<Item = u32> + 'a {
+        let from = self.lights[crossing.light - self.first_light].from;
+        let rank = |id: u32| self.priority.iter().position(|&p| p == id);
+        ctx.others(car, crossing).filter(move |(o, c)| {
+            let light = &self.lights[c.light - self.first_light];
+            if light.from == from {
+                return false;
+            }
+            let Some(time) = ctx.time_to_conflict(crossing, o, c) else {
+                return false;
+            };
+            let approaching = !o.is_stopped() && light.state(c.movement) != TrafficLightState::Red && time < ACCEPTED_GAP_S;
+            let before = match (rank(car.id()), rank(o.id())) {
+                (Some(a), Some(b)) => b < a,
+                (Some(_), None) => false,
+                (None, Some(_)) => true,
+                (None, None) => approaching,
+            };
+            o.has_entered_intersection() || before
+        })
+        .map(|(o, _)| o.id())
+    }
+
+    fn must_yield(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        self.yields_to(car, crossing, ctx).next().is_some()
+    }
+
+    /// Whether a car on a route crossing `car`'s at `crossing` is still in the junction,
+    /// clearing it after its light has turned.
+    fn clearing(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        ctx.others(car, crossing).any(|(o, c)| c.light != crossing.light && o.has_entered_intersection() && ctx.conflict(crossing, &c))
+    }
+}
+
+impl IntersectionController for SignalController {
+    /// Green lets the car go once the cars crossing its way have cleared the junction, unless it
+    /// cannot stop anymore. Permissive too, if it has nobody to give way to. On amber, the car
+    /// stops if it can brake comfortably before the line. Turning right on red, it stops at the
+    /// line then goes when the way is clear.
+    fn may_enter(&mut self, car: &Car, crossing: &Crossing, ctx: &Context) -> bool {
+        let light = &self.lights[crossing.light - self.first_light];
+        let decided = self.committed.get(&car.id()).filter(|(phase, _)| *phase == self.started).map(|&(_, go)| go);
+        match light.state(crossing.movement) {
+            TrafficLightState::Green => !can_stop(car, crossing) || !self.clearing(car, crossing, ctx),
+            TrafficLightState::Permissive => !self.must_yield(car, crossing, ctx),
+            TrafficLightState::Yellow => {
+                let go = decided.unwrap_or(!can_stop(car, crossing));
+                self.committed.insert(car.id(), (self.started, go));
+                go
+            },
+            TrafficLightState::Red if crossing.movement == Movement::Right && light.right_on_red => {
+                let stopped = decided.unwrap_or(false) || stopped_at_line(car, crossing);
+                if stopped {
+                    self.committed.insert(car.id(), (self.started, true));
+                }
+                stopped && !self.must_yield(car, crossing, ctx)
+            },
+            TrafficLightState::Red => decided.unwrap_or(false),
+        }
+    }
+
+    /// Advance by `dt` seconds, reading the loops of the junction and the lane queues, and
+    /// update its heads.
+    fn tick(&mut self, dt: f64, ctx: &Context) {
+        self.elapsed += dt;
+        let calls: Vec<bool> = self.phases.iter().map(|p| self.called(p, ctx.detectors)).collect();
+        self.since_call = if self.extended(ctx.detectors) { 0.0 } else { self.since_call + dt };
+
+        match self.ending {
+            None => self.ending = self.end_of_green(&calls, ctx.occupancy),
+            Some((end, next)) => {
+                let phase = &self.phases[self.phase];
+                let clearance = phase.yellow + phase.all_red;
+                if self.elapsed >= end + clearance {
+                    self.elapsed -= end + clearance;
+                    self.phase = next;
+                    self.started += 1;
+                    self.ending = None;
+                    self.since_call = 0.0;
+                }
+            },
+        }
+        self.apply();
+    }
+
+    /// Cars a permissive left or a right turn on red gives way to. Cars held by a red light
+    /// wait for no car.
+    fn waiting_for(&self, car: &Car, crossing: &Crossing, ctx: &Context) -> Vec<u32> {
+        let light = &self.lights[crossing.light - self.first_light];
+        let gives_way = match light.state(crossing.movement) {
+            TrafficLightState::Permissive => true,
+            TrafficLightState::Red => crossing.movement == Movement::Right && light.right_on_red,
+            _ => false,
+        };
+        match gives_way {
+            true => self.yields_to(car, crossing, ctx).collect(),
+            false => Vec::new(),
+        }
+    }
+
+    /// Only a car its head lets go, that waits for cross traffic rather than for its light.
+    fn give_priority(&mut self, car: &Car, crossing: &Crossing, _ctx: &Context) -> bool {
+        let state = self.lights[crossing.light - self.first_light].state(crossing.movement);
+        if !stopped_at_line(car, crossing) || !matches!(state, TrafficLightState::Green | TrafficLightState::Permissive) {
+            return false;
+        }
+        self.priority.retain(|&id| id != car.id());
+        self.priority.insert(0, car.id());
+        true
+    }
+
+    fn on_exit(&mut self, car: u32, _ctx: &Context) {
+        self.committed.remove(&car);
+        self.priority.retain(|&id| id != car);
+    }
+
+    fn lights(&self) -> &[TrafficLight] {
+        &self.lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intersection::testing::{drive, Junction};
+    use super::*;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn state(controller: &SignalController, from: Direction, movement: Movement) -> TrafficLightState {
+        controller.lights().iter().find(|l| l.from == from).unwrap().state(movement)
+    }
+
+    /// Tick `controller` for `seconds` with `cars` standing still, from `time`.
+    fn run(controller: &mut SignalController, junction: &Junction, cars: &[Car], time: &mut f64, seconds: f64) {
+        for _ in 0..(seconds / DT).round() as usize {
+            controller.tick(DT, &junction.context(*time, cars));
+            *time += DT;
+        }
+    }
+
+    #[test]
+    fn phase_change_goes_through_amber_and_all_red() {
+        let junction = Junction::new();
+        let plan = SignalPlan::default_for(&junction.network.nodes[0].layout);
+        let mut controller = SignalController::new(0, &junction.network, &plan);
+        let phase = plan.phases[0].clone();
+        let mut changes = Vec::new();
+        let mut last = (TrafficLightState::Green, TrafficLightState::Red);
+        let mut time = 0.0;
+        while time < phase.length() + 1.0 {
+            run(&mut controller, &junction, &[], &mut time, DT);
+            let now = (state(&controller, Direction::North, Movement::Straight), state(&controller, Direction::East, Movement::Straight));
+            if now != last {
+                changes.push((time, now));
+                last = now;
+            }
+        }
+        let expected = [
+            (phase.split, (TrafficLightState::Yellow, TrafficLightState::Red)),
+            (phase.split + phase.yellow, (TrafficLightState::Red, TrafficLightState::Red)),
+            (phase.length(), (TrafficLightState::Red, TrafficLightState::Green)),
+        ];
+        assert_eq!(changes.len(), expected.len());
+        for ((time, states), (at, expected)) in changes.into_iter().zip(expected) {
+            assert!((time - at).abs() <= DT + 1e-9, "{:?} at {} s instead of {} s", states, time, at);
+            assert_eq!(states, expected);
+        }
+    }
+
+    #[test]
+    fn amber_stops_the_cars_that_can_stop() {
+        let junction = Junction::new();
+        let plan = SignalPlan::default_for(&junction.network.nodes[0].layout);
+        let mut controller = SignalController::new(0, &junction.network, &plan);
+        let mut near = [junction.car(0, Direction::North, Direction::South)];
+        drive(&mut near, 1.8);
+        let cars = [near[0].clone(), junction.car(1, Direction::North, Direction::South)];
+        let crossing = cars[0].next_crossing().unwrap();
+        assert!(!can_stop(&cars[0], &crossing));
+        assert!(can_stop(&cars[1], &crossing));
+
+        let mut time = 0.0;
+        run(&mut controller, &junction, &cars, &mut time, plan.phases[0].split + 0.1);
+        assert_eq!(state(&controller, Direction::North, Movement::Straight), TrafficLightState::Yellow);
+        let ctx = junction.context(time, &cars);
+        assert!(controller.may_enter(&cars[0], &crossing, &ctx));
+        assert!(!controller.may_enter(&cars[1], &crossing, &ctx));
+
+        // The car too close to stop still goes once the light has turned red
+        run(&mut controller, &junction, &cars, &mut time, plan.phases[0].yellow);
+        assert_eq!(state(&controller, Direction::North, Movement::Straight), TrafficLightState::Red);
+        let ctx = junction.context(time, &cars);
+        assert!(controller.may_enter(&cars[0], &crossing, &ctx));
+        assert!(!controller.may_enter(&cars[1], &crossing, &ctx));
+    }
+
+    #[test]
+    fn amber_decisions_are_forgotten_once_the_next_phase_starts() {
+        let junction = Junction::new();
+        let plan = SignalPlan::default_for(&junction.network.nodes[0].layout);
+        let mut controller = SignalController::new(0, &junction.network, &plan);
+        let mut cars = [junction.car(0, Direction::North, Direction::South)];
+        drive(&mut cars, 1.8);
+        let crossing = cars[0].next_crossing().unwrap();
+
+        let mut time = 0.0;
+        run(&mut controller, &junction, &cars, &mut time, plan.phases[0].split + 0.1);
+        assert!(controller.may_enter(&cars[0], &crossing, &junction.context(time, &cars)));
+        run(&mut controller, &junction, &cars, &mut time, plan.phases[0].yellow + plan.phases[0].all_red);
+        assert_eq!(state(&controller, Direction::East, Movement::Straight), TrafficLightState::Green);
+        assert!(!controller.may_enter(&cars[0], &crossing, &junction.context(time, &cars)));
+    }
+
+    /// Plan under `control` with a ten second north-south phase, then an east-west one.
+    fn plan(junction: &Junction, control: &str, min_green: f64, passage: f64) -> SignalPlan {
+        let text = format!(
+            "control = \"{}\"\nmin_green = {}\npassage = {}\n\
+            [[phases]]\nmovements = [\"north\", \"south\"]\nsplit = 10\n\
+            [[phases]]\nmovements = [\"east\", \"west\"]\nsplit = 10\n",
+            control, min_green, passage,
+        );
+        SignalPlan::parse(&text, &junction.network).unwrap()
+    }
+
+    /// Seconds the green of the first phase lasts with `cars` standing where they are, if it
+    /// ends within a minute.
+    fn first_green(junction: &mut Junction, plan: &SignalPlan, cars: &[Car]) -> Option<f64> {
+        junction.observe(cars);
+        let mut controller = SignalController::new(0, &junction.network, plan);
+        let mut time = 0.0;
+        while time < 60.0 {
+            run(&mut controller, junction, cars, &mut time, DT);
+            if state(&controller, Direction::North, Movement::Straight) != TrafficLightState::Green {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn assert_near(time: Option<f64>, expected: f64) {
+        let time = time.expect("the green never ended");
+        assert!((time - expected).abs() <= DT + 1e-9, "green ended after {} s instead of {} s", time, expected);
+    }
+
+    #[test]
+    fn actuated_green_rests_without_a_call() {
+        let mut junction = Junction::new();
+        let plan = plan(&junction, "actuated", 2.0, 1.5);
+        assert_eq!(first_green(&mut junction, &plan, &[]), None);
+    }
+
+    #[test]
+    fn actuated_green_gaps_out() {
+        let mut junction = Junction::new();
+        let plan = plan(&junction, "actuated", 1.0, 3.0);
+        let waiting = junction.queue(Direction::East, 1, 0);
+        assert_near(first_green(&mut junction, &plan, &waiting), 3.0);
+    }
+
+    #[test]
+    fn actuated_green_lasts_its_minimum() {
+        let mut junction = Junction::new();
+        let plan = plan(&junction, "actuated", 4.0, 0.5);
+        let waiting = junction.queue(Direction::East, 1, 0);
+        assert_near(first_green(&mut junction, &plan, &waiting), 4.0);
+    }
+
+    #[test]
+    fn actuated_green_maxes_out() {
+        let mut junction = Junction::new();
+        let plan = plan(&junction, "actuated", 2.0, 1.5);
+        // A car standing on the north stop bar keeps extending the green
+        let mut cars = junction.queue(Direction::East, 1, 0);
+        cars.extend(junction.queue(Direction::North, 1, 1));
+        assert_near(first_green(&mut junction, &plan, &cars), 10.0);
+    }
+
+    /// Adaptive control over a north-south phase, then one for each of east and west.
+    fn three_phases(junction: &Junction) -> SignalPlan {
+        let text = "control = \"adaptive\"\nmin_green = 2\n\
+            [[phases]]\nmovements = [\"north\", \"south\"]\nsplit = 10\n\
+            [[phases]]\nmovements = [\"east\"]\nsplit = 10\n\
+            [[phases]]\nmovements = [\"west\"]\nsplit = 10\n";
+        SignalPlan::parse(text, &junction.network).unwrap()
+    }
+
+    #[test]
+    fn adaptive_green_goes_to_the_highest_pressure() {
+        let mut junction = Junction::new();
+        let plan = three_phases(&junction);
+        let mut cars = junction.queue(Direction::East, 1, 0);
+        cars.extend(junction.queue(Direction::West, 3, 1));
+        assert_near(first_green(&mut junction, &plan, &cars), plan.actuation.min_green);
+
+        let mut controller = SignalController::new(0, &junction.network, &plan);
+        let mut time = 0.0;
+        run(&mut controller, &junction, &cars, &mut time, plan.actuation.min_green + plan.phases[0].yellow + plan.phases[0].all_red + 0.1);
+        assert_eq!(state(&controller, Direction::West, Movement::Straight), TrafficLightState::Green);
+        assert_eq!(state(&controller, Direction::East, Movement::Straight), TrafficLightState::Red);
+    }
+
+    #[test]
+    fn adaptive_green_stays_while_its_pressure_is_highest() {
+        let mut junction = Junction::new();
+        let plan = three_phases(&junction);
+        let mut cars = junction.queue(Direction::North, 3, 0);
+        cars.extend(junction.queue(Direction::East, 1, 3));
+        // Kept up to the split, then handed to the only other phase with cars queued
+        assert_near(first_green(&mut junction, &plan, &cars), plan.phases[0].split);
+
+        let mut controller = SignalController::new(0, &junction.network, &plan);
+        let mut time = 0.0;
+        run(&mut controller, &junction, &cars, &mut time, plan.phases[0].length() + 0.1);
+        assert_eq!(state(&controller, Direction::East, Movement::Straight), TrafficLightState::Green);
+    }
+}
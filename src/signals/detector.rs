@@ -0,0 +1,127 @@
+use crate::cars::Car;
+use crate::geometry::Vec2;
+use crate::map::{Direction, Movement, Network};
+
+/// Length of the presence loop just behind the stop line, in pixels.
+const STOP_BAR_LENGTH: f64 = 60.0;
+/// Distance from the stop line to the pulse loop upstream, and its length, in pixels.
+const ADVANCE_DISTANCE: f64 = 150.0;
+const ADVANCE_LENGTH: f64 = 10.0;
+
+/// What a loop is used for.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum LoopKind {
+    /// Long loop at the stop line, occupied while cars queue on it.
+    StopBar,
+    /// Short loop upstream, pulsing as cars drive over it.
+    Advance,
+}
+
+/// Virtual inductive loop on an approach lane.
+#[derive(Debug,Clone)]
+pub struct Detector {
+    pub kind: LoopKind,
+    /// Junction, approach and lane the loop lies on, as in `Crossing`.
+    pub node: usize,
+    pub light: usize,
+    pub lane: usize,
+    pub from: Direction,
+    /// Movements allowed from the lane.
+    pub movements: Vec<Movement>,
+    /// Ends of the loop on the canvas, downstream first.
+    pub ends: (Vec2, Vec2),
+    /// Start and end of the loop, in pixels before the stop line.
+    near: f64,
+    far: f64,
+
+    /// Whether a car is over the loop.
+    occupied: bool,
+    /// Whether a car drove onto the loop during the last step.
+    pulse: bool,
+}
+
+impl Detector {
+    pub fn occupied(&self) -> bool {
+        self.occupied
+    }
+
+    pub fn pulse(&self) -> bool {
+        self.pulse
+    }
+
+    /// Whether the loop reports a car, standing or just arriving.
+    pub fn actuated(&self) -> bool {
+        self.occupied || self.pulse
+    }
+
+    /// Whether the loop lies on a lane carrying `movement` from `from`.
+    pub fn serves(&self, from: Direction, movement: Movement) -> bool {
+        self.from == from && self.movements.contains(&movement)
+    }
+}
+
+/// Every loop of the network: a stop bar and an advance loop on each approach lane.
+#[derive(Debug,Clone)]
+pub struct Detectors {
+    loops: Vec<Detector>,
+}
+
+impl Detectors {
+    pub fn new(network: &Network) -> Self {
+        let mut loops = Vec::new();
+        for (n, node) in network.nodes.iter().enumerate() {
+            for (a, approach) in node.layout.approaches.iter().enumerate() {
+                for (l, lane) in approach.lanes.iter().enumerate() {
+                    let length = (lane.lane.end - lane.lane.start).length();
+                    let back = -lane.lane.direction();
+                    for (kind, near, far) in [
+                        (LoopKind::StopBar, 0.0, STOP_BAR_LENGTH),
+                        (LoopKind::Advance, ADVANCE_DISTANCE, ADVANCE_DISTANCE + ADVANCE_LENGTH),
+                    ] {
+                        // Short links get their advance loop at the start of the lane
+                        let (near, far) = (near.min(length - (far - near)).max(0.0), far.min(length));
+                        loops.push(Detector {
+                            kind,
+                            node: n,
+                            light: node.first_light + a,
+                            lane: l,
+                            from: approach.from,
+                            movements: lane.movements.clone(),
+                            ends: (lane.lane.end + back * near, lane.lane.end + back * far),
+                            near,
+                            far,
+                            occupied: false,
+                            pulse: false,
+                        });
+                    }
+                }
+            }
+        }
+        Self { loops }
+    }
+
+    /// Read the loops after the cars have moved.
+    pub fn update(&mut self, cars: &[Car]) {
+        for d in &mut self.loops {
+            let occupied = cars.iter().any(|car| {
+                let Some(c) = car.next_crossing().filter(|c| c.light == d.light && c.lane == d.lane) else {
+                    return false;
+                };
+                let half = car.w_l().1 as f64 / 2.0;
+                let to_stop = c.stop - car.travelled();
+                to_stop - half < d.far && to_stop + half > d.near
+            });
+            d.pulse = occupied && !d.occupied;
+            d.occupied = occupied;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Detector> {
+        self.loops.iter()
+    }
+
+    /// Loops of junction `node`.
+    pub fn at(&self, node: usize) -> impl Iterator<Item = &Detector> {
+        self.loops.iter().filter(move |d| d.node == node)
+    }
+}
@@ -0,0 +1,7 @@
+mod controller;
+mod detector;
+mod plan;
+
+pub use controller::*;
+pub use detector::*;
+pub use plan::*;
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::map::{ConflictGrid, Direction, Layout, Movement, Network, TrafficLightState};
+
+/// Green time of each phase of the default plan, in seconds.
+const DEFAULT_SPLIT_S: f64 = 200.0 / 60.0;
+/// Amber shown to the movements a phase stops, in seconds.
+const DEFAULT_YELLOW_S: f64 = 1.0;
+/// Time everything stopped stays red before the next phase starts, so the box can clear, in seconds.
+const DEFAULT_ALL_RED_S: f64 = 1.0;
+/// Shortest green of an actuated phase, in seconds.
+const DEFAULT_MIN_GREEN_S: f64 = 2.0;
+/// Time without a car on the loops of an actuated phase after which its green ends, in seconds.
+const DEFAULT_PASSAGE_S: f64 = 1.5;
+
+/// How a controller decides when a phase ends.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Control {
+    /// Every phase gets its split, cycle after cycle.
+    Fixed,
+    /// Green lasts from `min_green` up to the split, ended early when no car reaches the loops
+    /// for `passage` seconds. Phases nobody waits for are skipped.
+    Actuated,
+    /// Max-pressure: after `min_green`, green goes to the phase whose movements have the longest
+    /// queues relative to the roads they feed, the split capping how long one phase may keep it.
+    Adaptive,
+}
+
+impl FromStr for Control {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Control::Fixed),
+            "actuated" => Ok(Control::Actuated),
+            "adaptive" => Ok(Control::Adaptive),
+            _ => Err(format!("unknown control `{}`, expected fixed, actuated or adaptive", s)),
+        }
+    }
+}
+
+/// Timings of an actuated or adaptive controller, in seconds.
+#[derive(Debug,Clone,Copy)]
+pub struct Actuation {
+    pub min_green: f64,
+    pub passage: f64,
+}
+
+/// Movements released together, and for how long.
+#[derive(Debug,Clone)]
+pub struct Phase {
+    /// Approach side and movement of every movement with green.
+    pub movements: Vec<(Direction, Movement)>,
+    /// Green time, in seconds. Longest green under actuated control.
+    pub split: f64,
+    /// Amber shown after the green to the movements the next phase stops, in seconds.
+    pub yellow: f64,
+    /// All-red time after the amber, in seconds.
+    pub all_red: f64,
+}
+
+impl Phase {
+    /// What the phase shows to `movement` from `from`: a left turn released with the opposite
+    /// straight or right movements is permissive, anything else released is protected.
+    pub fn indication(&self, from: Direction, movement: Movement) -> TrafficLightState {
+        if !self.releases(from, movement) {
+            return TrafficLightState::Red;
+        }
+        let opposed = movement == Movement::Left &&
+            [Movement::Straight, Movement::Right].iter().any(|&m| self.releases(from.opposite(), m));
+        if opposed { TrafficLightState::Permissive } else { TrafficLightState::Green }
+    }
+
+    /// Green and clearance time, in seconds.
+    pub fn length(&self) -> f64 {
+        self.split + self.yellow + self.all_red
+    }
+
+    pub fn releases(&self, from: Direction, movement: Movement) -> bool {
+        self.movements.contains(&(from, movement))
+    }
+}
+
+/// Signal plan shared by the junctions of a network: phases run in order, under fixed time
+/// each junction shifted by its offset.
+#[derive(Debug,Clone)]
+pub struct SignalPlan {
+    pub control: Control,
+    pub actuation: Actuation,
+    pub phases: Vec<Phase>,
+    /// Start of the first phase at each junction, in seconds into the cycle, by junction name.
+    pub offsets: HashMap<String, f64>,
+    /// Whether right turns may go on red after stopping.
+    pub right_on_red: bool,
+}
+
+// What the file looks like, turned into a `SignalPlan` by `SignalPlan::parse`.
+
+#[derive(Deserialize)]
+struct PhaseFile {
+    movements: Vec<String>,
+    split: f64,
+    yellow: Option<f64>,
+    all_red: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct PlanFile {
+    control: Option<Control>,
+    min_green: Option<f64>,
+    passage: Option<f64>,
+    cycle: Option<f64>,
+    yellow: Option<f64>,
+    all_red: Option<f64>,
+    #[serde(default)]
+    right_on_red: bool,
+    phases: Vec<PhaseFile>,
+    #[serde(default)]
+    offsets: HashMap<String, f64>,
+}
+
+/// Movements named by `text`: every movement of an approach (`north`) or one of them (`north:left`).
+fn movements(text: &str) -> Result<Vec<(Direction, Movement)>, String> {
+    match text.split_once(':') {
+        Some((side, movement)) => Ok(vec![(side.parse()?, movement.parse()?)]),
+        None => {
+            let side: Direction = text.parse()?;
+            Ok(Movement::ALL.iter().map(|&m| (side, m)).collect())
+        },
+    }
+}
+
+impl SignalPlan {
+    /// Two phases of equal length: the approaches whose light starts green, then the others.
+    pub fn default_for(layout: &Layout) -> Self {
+        let phase = |state: TrafficLightState| Phase {
+            movements: layout.approaches.iter()
+                .filter(|a| a.initial == state)
+                .flat_map(|a| Movement::ALL.iter().map(move |&m| (a.from, m)))
+                .collect(),
+            split: DEFAULT_SPLIT_S,
+            yellow: DEFAULT_YELLOW_S,
+            all_red: DEFAULT_ALL_RED_S,
+        };
+        Self {
+            control: Control::Fixed,
+            actuation: Actuation { min_green: DEFAULT_MIN_GREEN_S, passage: DEFAULT_PASSAGE_S },
+            phases: vec![phase(TrafficLightState::Green), phase(TrafficLightState::Red)],
+            offsets: HashMap::new(),
+            right_on_red: false,
+        }
+    }
+
+    pub fn load(path: &str, network: &Network) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text, network).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str, network: &Network) -> Result<Self, String> {
+        let file: PlanFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut phases = Vec::new();
+        for p in file.phases {
+            let yellow = p.yellow.or(file.yellow).unwrap_or(DEFAULT_YELLOW_S);
+            let all_red = p.all_red.or(file.all_red).unwrap_or(DEFAULT_ALL_RED_S);
+            if p.split <= 0.0 {
+                return Err(String::from("phase splits must be positive"));
+            }
+            if yellow < 0.0 || all_red < 0.0 {
+                return Err(String::from("clearance intervals cannot be negative"));
+            }
+            let mut released = Vec::new();
+            for m in &p.movements {
+                released.extend(movements(m)?);
+            }
+            phases.push(Phase { movements: released, split: p.split, yellow, all_red });
+        }
+        let actuation = Actuation {
+            min_green: file.min_green.unwrap_or(DEFAULT_MIN_GREEN_S),
+            passage: file.passage.unwrap_or(DEFAULT_PASSAGE_S),
+        };
+        if actuation.min_green < 0.0 || actuation.passage <= 0.0 {
+            return Err(String::from("min_green cannot be negative and passage must be positive"));
+        }
+        let plan = Self {
+            control: file.control.unwrap_or(Control::Fixed),
+            actuation,
+            phases,
+            offsets: file.offsets,
+            right_on_red: file.right_on_red,
+        };
+        if plan.phases.is_empty() {
+            return Err(String::from("a plan needs at least one phase"));
+        }
+        if let Some(cycle) = file.cycle {
+            if (cycle - plan.cycle()).abs() > 1e-6 {
+                return Err(format!("the phases and their clearance add up to {} s, not the {} s cycle", plan.cycle(), cycle));
+            }
+        }
+        if let Some(name) = plan.offsets.keys().find(|&name| !network.nodes.iter().any(|n| n.name == *name)) {
+            return Err(format!("offset given for unknown junction `{}`", name));
+        }
+        Ok(plan)
+    }
+
+    /// Make sure no phase gives a protected green to two movements whose routes through a
+    /// junction of `network` share a cell of its conflict grid, among `grids`.
+    pub fn check(&self, network: &Network, grids: &[ConflictGrid]) -> Result<(), String> {
+        let name = |(from, m): (Direction, Movement)| format!("{:?}:{:?}", from, m).to_lowercase();
+        for (node, grid) in network.nodes.iter().zip(grids) {
+            for (p, phase) in self.phases.iter().enumerate() {
+                let protected: Vec<(Direction, Movement)> = phase.movements.iter().copied()
+                    .filter(|&(from, m)| phase.indication(from, m) == TrafficLightState::Green)
+                    .collect();
+                for (i, &a) in protected.iter().enumerate() {
+                    if let Some(&b) = protected[i + 1..].iter().find(|b| b.0 != a.0 && grid.movements_conflict(a, **b)) {
+                        return Err(format!("phase {} gives protected greens to `{}` and `{}`, which cross at `{}`",
+                            p + 1, name(a), name(b), node.name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Length of the cycle, clearance intervals included, in seconds.
+    pub fn cycle(&self) -> f64 {
+        self.phases.iter().map(Phase::length).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAR: (u32, u32) = (32, 45);
+
+    fn corridor() -> (Network, Vec<ConflictGrid>) {
+        let network = Network::load("assets/corridor.toml").unwrap();
+        let grids = network.nodes.iter().map(|n| ConflictGrid::new(&n.layout, CAR)).collect();
+        (network, grids)
+    }
+
+    fn phases(phases: &[&str]) -> String {
+        phases.iter().map(|m| format!("[[phases]]\nmovements = [{}]\nsplit = 5\n", m)).collect()
+    }
+
+    #[test]
+    fn shipped_and_default_plans_pass() {
+        let (network, grids) = corridor();
+        let plan = SignalPlan::load("assets/green_wave.toml", &network).unwrap();
+        assert_eq!(plan.check(&network, &grids), Ok(()));
+        let plan = SignalPlan::default_for(&network.nodes[0].layout);
+        assert_eq!(plan.check(&network, &grids), Ok(()));
+    }
+
+    #[test]
+    fn crossing_protected_movements_are_refused() {
+        let (network, grids) = corridor();
+        let plan = SignalPlan::parse(&phases(&["\"north\", \"east\""]), &network).unwrap();
+        assert!(plan.check(&network, &grids).is_err());
+        let plan = SignalPlan::parse(&phases(&["\"east:left\", \"west:left\""]), &network).unwrap();
+        assert!(plan.check(&network, &grids).is_err());
+    }
+
+    #[test]
+    fn permissive_left_is_not_a_conflict() {
+        let (network, grids) = corridor();
+        let plan = SignalPlan::parse(&phases(&["\"east\", \"west\"", "\"north\", \"south\""]), &network).unwrap();
+        assert_eq!(plan.phases[0].indication(Direction::East, Movement::Left), TrafficLightState::Permissive);
+        assert_eq!(plan.phases[0].indication(Direction::East, Movement::Straight), TrafficLightState::Green);
+        assert_eq!(plan.phases[0].indication(Direction::North, Movement::Straight), TrafficLightState::Red);
+        assert_eq!(plan.check(&network, &grids), Ok(()));
+    }
+
+    #[test]
+    fn bad_plans_are_rejected() {
+        let (network, _) = corridor();
+        assert!(SignalPlan::parse("", &network).is_err());
+        assert!(SignalPlan::parse("[[phases]]\nmovements = [\"north\"]\nsplit = 0\n", &network).is_err());
+        assert!(SignalPlan::parse(&format!("cycle = 10\n{}", phases(&["\"north\""])), &network).is_err());
+        assert!(SignalPlan::parse(&format!("cycle = 7\n{}", phases(&["\"north\""])), &network).is_ok());
+        assert!(SignalPlan::parse(&format!("offsets = {{ nowhere = 3 }}\n{}", phases(&["\"north\""])), &network).is_err());
+        assert!(SignalPlan::parse(&phases(&["\"north:uturn\""]), &network).is_err());
+    }
+}
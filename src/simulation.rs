@@ -0,0 +1,734 @@
+use std::collections::{HashMap, VecDeque};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::cars::{Car, Dynamics, Idm, UpdateState, TURN_VELOCITY};
+use crate::deadlock::{Deadlock, Recovery, WaitForGraph, DEADLOCK_S};
+use crate::demand::Demand;
+use crate::geometry::{Obb, Vec2};
+use crate::intersection::{can_stop, distance_to_line, exit_blocked, past_line, stopped_at_line, Context, IntersectionController, Mode, APPROACH_DISTANCE};
+use crate::map::{self, ConflictGrid, Crossing, Direction, Network, Port, Routing};
+use crate::occupancy::{LaneId, Occupancy, DEFAULT_LANE_CAP};
+use crate::signals::{Control, Detectors, SignalPlan};
+
+const CAR_SIZE: (u32,u32) = (32,45);
+/// Free road needed in front of a new car for it to enter, so that it can still brake behind a queue.
+const SPAWN_CLEARANCE: f64 = 100.0;
+/// Spread of the acceleration, braking and headway parameters between vehicles.
+const DYNAMICS_SPREAD: f64 = 0.15;
+/// Under this speed a car in a junction counts as sitting there, the others stop short of it.
+const SITTING_SPEED: f64 = TURN_VELOCITY / 4.0;
+
+/// Parameters fixed for the whole run.
+#[derive(Debug,Clone,Copy)]
+pub struct Config {
+    /// Length of one simulation step, in seconds.
+    pub dt: f64,
+    /// Seed of the simulation RNG, the same seed replays the same run.
+    pub seed: u64,
+    /// How the routes of new cars are chosen.
+    pub routing: Routing,
+    /// Cars allowed on a lane before new cars are held back from entering it.
+    pub lane_cap: usize,
+    /// How the junctions are run at start.
+    pub mode: Mode,
+    /// Control mode of the signals, overriding the one of the signal plans.
+    pub control: Option<Control>,
+    /// What is done when cars are found waiting on each other in a circle.
+    pub on_deadlock: Recovery,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dt: 1.0 / 60.0,
+            seed: rand::random(),
+            routing: Routing::FreeFlow,
+            lane_cap: DEFAULT_LANE_CAP,
+            mode: Mode::Signals,
+            control: None,
+            on_deadlock: Recovery::Priority,
+        }
+    }
+}
+
+#[derive(Debug,Default,Clone,Copy)]
+pub struct Stats {
+    pub spawned: u32,
+    pub passed: u32,
+    pub collisions: u32,
+    /// Seconds spent standing still, summed over all cars.
+    pub stopped: f64,
+}
+
+impl Stats {
+    /// Mean time a car on the road has spent standing still, in seconds.
+    pub fn stopped_per_car(&self) -> f64 {
+        self.stopped / self.spawned.max(1) as f64
+    }
+}
+
+/// Cars asked for on one entry lane, waiting off-screen for their spawn point to be clear.
+pub struct EntryQueue {
+    pub port: Port,
+    pub lane: usize,
+    /// Light guarding the approach, as in `Crossing::light`.
+    light: usize,
+    waiting: VecDeque<Car>,
+}
+
+impl EntryQueue {
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+/// The whole road network state, stepped `config.dt` seconds at a time.
+/// Nothing in here touches a window or a canvas, renderers only read it.
+pub struct Simulation {
+    config: Config,
+    network: Network,
+    rng: StdRng,
+    cars: Vec<Car>,
+    mode: Mode,
+    /// Plan run by the signals of every junction, their default two-phase plan if `None`.
+    plan: Option<SignalPlan>,
+    /// One per junction, in the order of `network.nodes`.
+    controllers: Vec<Box<dyn IntersectionController>>,
+    /// Junction crossing each car has been announced to, until it is out of it.
+    announced: HashMap<u32, Crossing>,
+    /// Car past the junction that each car held there for want of room would queue behind.
+    blocked: HashMap<u32, u32>,
+    detectors: Detectors,
+    /// Time each car standing still has been so since.
+    stopped_since: HashMap<u32, f64>,
+    /// Every deadlock found, in order.
+    deadlocks: Vec<Deadlock>,
+    /// Deadlocks found that no car of has moved since, by index in `deadlocks`, with their cars
+    /// sorted.
+    standing: Vec<(usize, Vec<u32>)>,
+    /// The deadlock that failed the run, by index in `deadlocks`.
+    failed: Option<usize>,
+    /// Cells swept by every route of each junction, in the order of `network.nodes`.
+    grids: Vec<ConflictGrid>,
+    demand: Option<Demand>,
+    queues: Vec<EntryQueue>,
+    occupancy: Occupancy,
+
+    tick: u64,
+    next_id: u32,
+
+    stats: Stats,
+}
+
+impl Simulation {
+    pub fn new(config: Config, network: Network) -> Self {
+        let mut queues = Vec::new();
+        for &port in &network.entries {
+            let node = &network.nodes[port.node];
+            if let Ok((index, approach)) = node.layout.approach(port.side) {
+                for lane in 0..approach.lanes.len() {
+                    queues.push(EntryQueue { port, lane, light: node.first_light + index, waiting: VecDeque::new() });
+                }
+            }
+        }
+        let mut sim = Self {
+            config,
+            rng: StdRng::seed_from_u64(config.seed),
+            cars: Vec::new(),
+            mode: config.mode,
+            plan: None,
+            controllers: Vec::new(),
+            announced: HashMap::new(),
+            blocked: HashMap::new(),
+            detectors: Detectors::new(&network),
+            stopped_since: HashMap::new(),
+            deadlocks: Vec::new(),
+            standing: Vec::new(),
+            failed: None,
+            grids: network.nodes.iter().map(|n| ConflictGrid::new(&n.layout, CAR_SIZE)).collect(),
+            occupancy: Occupancy::new(&network, config.lane_cap),
+            network,
+            demand: None,
+            queues,
+            tick: 0,
+            next_id: 0,
+            stats: Stats::default(),
+        };
+        sim.build_controllers();
+        sim
+    }
+
+    /// Run `plan` at every junction instead of the default two-phase plans, from the start of its
+    /// cycle. Fails, leaving the junctions as they were, if it gives a protected green to routes
+    /// crossing each other.
+    pub fn set_signal_plan(&mut self, plan: &SignalPlan) -> Result<(), String> {
+        plan.check(&self.network, &self.grids)?;
+        self.plan = Some(plan.clone());
+        self.build_controllers();
+        Ok(())
+    }
+
+    /// Run every junction in `mode` from now on, with fresh controllers.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.build_controllers();
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Controller of every junction, in the order of `network.nodes`.
+    pub fn controllers(&self) -> &[Box<dyn IntersectionController>] {
+        &self.controllers
+    }
+
+    pub fn grids(&self) -> &[ConflictGrid] {
+        &self.grids
+    }
+
+    /// New controllers for every junction in the current mode. Cars on their way are announced
+    /// to them again.
+    fn build_controllers(&mut self) {
+        self.controllers = (0..self.network.nodes.len())
+            .map(|n| {
+                let mut plan = self.plan.clone().unwrap_or_else(|| SignalPlan::default_for(&self.network.nodes[n].layout));
+                plan.control = self.config.control.unwrap_or(plan.control);
+                self.mode.controller(n, &self.network, &self.grids[n], &plan)
+            })
+            .collect();
+        self.announced.clear();
+    }
+
+    /// What the controller of junction `node` gets to read at `time`.
+    fn context(&self, node: usize, time: f64) -> Context<'_> {
+        Context {
+            time,
+            cars: &self.cars,
+            network: &self.network,
+            grid: &self.grids[node],
+            detectors: &self.detectors,
+            occupancy: &self.occupancy,
+        }
+    }
+
+    /// Announce the cars coming up to a junction to its controller, and hold at their stop line
+    /// the ones it does not let in yet, and the ones that would have to stop in the junction for
+    /// want of room past it while they can still stop before it.
+    fn control_junctions(&mut self, time: f64) {
+        let mut controllers = std::mem::take(&mut self.controllers);
+        let mut announced = std::mem::take(&mut self.announced);
+        let mut held = Vec::with_capacity(self.cars.len());
+        self.blocked.clear();
+        for car in &self.cars {
+            let Some(crossing) = car.next_crossing() else {
+                held.push(false);
+                continue;
+            };
+            let ctx = self.context(crossing.node, time);
+            let controller = &mut controllers[crossing.node];
+            let known = announced.get(&car.id()).is_some_and(|c| c.stop == crossing.stop);
+            if !known && distance_to_line(car, &crossing) < APPROACH_DISTANCE {
+                controller.on_approach(car, &crossing, &ctx);
+                announced.insert(car.id(), crossing);
+            }
+            match exit_blocked(car, &crossing, &self.cars).filter(|_| can_stop(car, &crossing)) {
+                Some(blocker) => {
+                    self.blocked.insert(car.id(), blocker);
+                    held.push(true);
+                },
+                None => held.push(!controller.may_enter(car, &crossing, &ctx)),
+            }
+        }
+        for (car, held) in self.cars.iter_mut().zip(held) {
+            car.set_held(held);
+        }
+        self.controllers = controllers;
+        self.announced = announced;
+    }
+
+    /// Have every car in a junction stop short of the first cell of its route that a car sitting
+    /// in the junction from another approach covers, rather than drive into it. A car deeper into
+    /// the junction than this one goes first: its remaining cells are kept clear too.
+    fn yield_in_junctions(&mut self, time: f64) {
+        let yields: Vec<Option<(f64, u32)>> = self.cars.iter().map(|car| {
+            let crossing = car.crossing().filter(|_| car.has_entered_intersection())?;
+            let ctx = self.context(crossing.node, time);
+            let routes = ctx.grid.routes();
+            let x = past_line(car, &crossing);
+            let ahead: Vec<_> = routes[ctx.route(&crossing)?].cells.iter().filter(|span| span.enter > x).collect();
+            ctx.others(car, &crossing)
+                .filter(|(o, c)| c.light != crossing.light && o.has_entered_intersection() && o.speed() < SITTING_SPEED)
+                .filter_map(|(o, c)| {
+                    let at = past_line(o, &c);
+                    let first = at > x;
+                    let kept: Vec<usize> = routes[ctx.route(&c)?].cells.iter()
+                        .filter(|span| span.leave >= at && (first || span.enter <= at))
+                        .map(|span| span.cell)
+                        .collect();
+                    ahead.iter()
+                        .filter(|span| kept.contains(&span.cell))
+                        .map(|span| (crossing.stop + span.enter, o.id()))
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+        }).collect();
+        for (car, yield_to) in self.cars.iter_mut().zip(yields) {
+            car.set_yield_to(yield_to);
+        }
+    }
+
+    /// Tell the controllers about the cars that have left their junction, or the network, then
+    /// advance them by `dt` seconds.
+    fn tick_junctions(&mut self, dt: f64, time: f64) {
+        let mut controllers = std::mem::take(&mut self.controllers);
+        let left: Vec<(u32, Crossing)> = self.announced.iter()
+            .filter(|(&id, crossing)| self.cars.iter().find(|c| c.id() == id)
+                .is_none_or(|c| c.crossing().is_none_or(|c| c.stop != crossing.stop)))
+            .map(|(&id, &crossing)| (id, crossing))
+            .collect();
+        for (id, crossing) in left {
+            self.announced.remove(&id);
+            controllers[crossing.node].on_exit(id, &self.context(crossing.node, time));
+        }
+        for (node, controller) in controllers.iter_mut().enumerate() {
+            controller.tick(dt, &self.context(node, time));
+        }
+        self.controllers = controllers;
+    }
+
+    /// Spawn cars from `demand` as the simulation runs, on top of the ones asked for by hand.
+    pub fn set_demand(&mut self, demand: Demand) {
+        self.demand = Some(demand);
+    }
+
+    pub fn demand(&self) -> Option<&Demand> {
+        self.demand.as_ref()
+    }
+
+    /// Ask for a car entering the network at `entry` and leaving it at `exit`, on lanes allowed
+    /// for its movements. It waits in the queue of its entry lane until there is room to enter.
+    /// Returns whether the trip was possible.
+    pub fn spawn_car(&mut self, entry: Port, exit: Port) -> bool {
+        let load = match self.config.routing {
+            Routing::FreeFlow => Vec::new(),
+            Routing::Congested => self.link_load(),
+        };
+        let car = self.network.route(entry, exit, &load, &mut self.rng)
+            .and_then(|route| map::spawn_car(route, CAR_SIZE.0, CAR_SIZE.1));
+        let mut car = match car {
+            Ok(c) => c,
+            Err(_) => return false,
+        };
+        car.set_id(self.next_id);
+        car.set_texture(&mut self.rng);
+        let k = self.rng.gen_range(1.0 - DYNAMICS_SPREAD..=1.0 + DYNAMICS_SPREAD);
+        car.set_dynamics(Dynamics::default().scaled(k));
+        let idm = Idm::default();
+        car.set_idm(Idm {
+            time_headway: idm.time_headway * self.rng.gen_range(1.0 - DYNAMICS_SPREAD..=1.0 + DYNAMICS_SPREAD),
+            ..idm
+        });
+        let Some(first) = car.crossings().first().copied() else { return false };
+        let Some(queue) = self.queues.iter_mut().find(|q| q.light == first.light && q.lane == first.lane) else {
+            return false;
+        };
+        self.next_id += 1;
+        queue.waiting.push_back(car);
+        true
+    }
+
+    /// Whether `car`, at the start of its path, would have `SPAWN_CLEARANCE` of free road in front of it.
+    fn entry_clear(&self, car: &Car) -> bool {
+        let forward = Vec2::from_angle(car.heading());
+        let footprint = car.obb();
+        let zone = Obb::new(
+            footprint.center + forward * (SPAWN_CLEARANCE / 2.0),
+            footprint.heading,
+            footprint.length + SPAWN_CLEARANCE,
+            footprint.width,
+        );
+        self.cars.iter().all(|c| !c.obb().intersects(&zone))
+    }
+
+    /// Let the first car of every entry queue in if its spawn point is clear and its lane is not full.
+    fn release_queues(&mut self) {
+        for i in 0..self.queues.len() {
+            let lane = LaneId::Approach { light: self.queues[i].light, lane: self.queues[i].lane };
+            if self.occupancy.is_full(lane) || !self.queues[i].waiting.front().is_some_and(|c| self.entry_clear(c)) {
+                continue;
+            }
+            if let Some(car) = self.queues[i].waiting.pop_front() {
+                self.cars.push(car);
+                self.stats.spawned += 1;
+            }
+        }
+    }
+
+    /// Spawn a car entering at `entry` with a random reachable destination.
+    fn spawn_car_at(&mut self, entry: Port) -> bool {
+        let destinations = self.network.destinations(entry);
+        if destinations.is_empty() {
+            return false;
+        }
+        let exit = destinations[self.rng.gen_range(0..destinations.len())];
+        self.spawn_car(entry, exit)
+    }
+
+    /// Spawn a car coming from the `from` side of the network, at a random entry on that side.
+    pub fn spawn_car_from(&mut self, from: Direction) -> bool {
+        let entries: Vec<Port> = self.network.entries.iter().copied().filter(|e| e.side == from).collect();
+        if entries.is_empty() {
+            return false;
+        }
+        let entry = entries[self.rng.gen_range(0..entries.len())];
+        self.spawn_car_at(entry)
+    }
+
+    /// Spawn a car from a random entry to a random destination.
+    pub fn spawn_random_car(&mut self) -> bool {
+        if self.network.entries.is_empty() {
+            return false;
+        }
+        let entry = self.network.entries[self.rng.gen_range(0..self.network.entries.len())];
+        self.spawn_car_at(entry)
+    }
+
+    /// Number of cars driving down each link of the network.
+    pub fn link_load(&self) -> Vec<usize> {
+        self.network.links.iter()
+            .map(|l| self.network.light(l.to, l.side.opposite()).map_or(0, |light| self.occupancy.approach(light)))
+            .collect()
+    }
+
+    pub fn step(&mut self) {
+        let time = self.time();
+        if let Some(demand) = &mut self.demand {
+            for trip in demand.arrivals(time, &self.network, &mut self.rng) {
+                self.spawn_car(trip.entry, trip.exit);
+            }
+        }
+        self.release_queues();
+        self.control_junctions(time);
+        self.yield_in_junctions(time);
+
+        let snapshot = self.cars.clone();
+        let mut finished = Vec::new();
+        for c in self.cars.iter_mut() {
+            match c.update(self.config.dt, &snapshot) {
+                UpdateState::Finished => {
+                    self.stats.passed += 1;
+                    finished.push(c.id());
+                },
+                UpdateState::Collided => {
+                    self.stats.collisions += 1;
+                    finished.push(c.id());
+                },
+                UpdateState::Waiting => self.stats.stopped += self.config.dt,
+                _ => {},
+            }
+        }
+        self.cars.retain(|c| !finished.contains(&c.id()));
+
+        self.occupancy.update(&self.cars, &self.network);
+        self.detectors.update(&self.cars);
+        self.tick_junctions(self.config.dt, time + self.config.dt);
+        self.detect_deadlocks(time + self.config.dt);
+        self.tick += 1;
+    }
+
+    /// Who waits for whom among the cars standing still for at least `DEADLOCK_S` at `time`:
+    /// the car each one follows, and if it is held at its stop line the car it would queue behind
+    /// past the junction or the cars its controller makes it give way to.
+    fn wait_for_graph(&self, time: f64) -> WaitForGraph {
+        let stuck = |id: u32| self.stopped_since.get(&id).is_some_and(|&since| time - since >= DEADLOCK_S);
+        let mut graph = WaitForGraph::default();
+        for car in self.cars.iter().filter(|c| stuck(c.id())) {
+            if let Some(other) = car.following().filter(|&o| stuck(o)) {
+                graph.add(car.id(), other);
+            }
+            if let Some(&other) = self.blocked.get(&car.id()).filter(|&&o| stuck(o)) {
+                graph.add(car.id(), other);
+            }
+            let Some(crossing) = car.next_crossing().filter(|_| car.is_held()) else { continue };
+            let ctx = self.context(crossing.node, time);
+            for other in self.controllers[crossing.node].waiting_for(car, &crossing, &ctx) {
+                if stuck(other) {
+                    graph.add(car.id(), other);
+                }
+            }
+        }
+        graph
+    }
+
+    /// Look for cars waiting on each other in a circle and report each cycle once. Under
+    /// `Recovery::Priority`, the one held first of its lane at its stop line by its controller
+    /// for the longest is given priority over the others, if the controller can. The run fails
+    /// if none can be, under `Recovery::Abort`, or if a cycle reported is still there, nobody in
+    /// it having moved, `DEADLOCK_S` after.
+    fn detect_deadlocks(&mut self, time: f64) {
+        self.stopped_since.retain(|id, _| self.cars.iter().any(|c| c.id() == *id && c.is_stopped()));
+        for car in self.cars.iter().filter(|c| c.is_stopped()) {
+            self.stopped_since.entry(car.id()).or_insert(time);
+        }
+
+        let deadlocks = &self.deadlocks;
+        let stopped_since = &self.stopped_since;
+        self.standing.retain(|(i, cars)| {
+            cars.iter().all(|id| stopped_since.get(id).is_some_and(|&since| since <= deadlocks[*i].time))
+        });
+        // Cut the cycles reported already, to look for one that is not
+        let mut graph = self.wait_for_graph(time);
+        let cycle = loop {
+            let Some(cycle) = graph.find_cycle() else { return };
+            let mut cars = cycle.clone();
+            cars.sort_unstable();
+            match self.standing.iter().find(|(_, c)| *c == cars) {
+                None => break cycle,
+                Some(&(i, _)) if time - self.deadlocks[i].time >= DEADLOCK_S => {
+                    self.failed.get_or_insert(i);
+                    return;
+                },
+                Some(_) => graph.remove(cycle[0], cycle[1 % cycle.len()]),
+            }
+        };
+        let released = match self.config.on_deadlock {
+            Recovery::Priority => self.give_priority(&cycle, time),
+            Recovery::Abort => None,
+        };
+        let index = self.deadlocks.len();
+        let mut cars = cycle.clone();
+        cars.sort_unstable();
+        self.deadlocks.push(Deadlock { time, cars: cycle, released });
+        match released {
+            Some(_) => self.standing.push((index, cars)),
+            None => {
+                self.failed.get_or_insert(index);
+            },
+        }
+    }
+
+    /// Give priority to break `cycle` to the car of it held first of its lane at its stop line
+    /// for the longest, waiting on its controller rather than on the car ahead or on room past
+    /// the junction, and that its controller can give priority to.
+    fn give_priority(&mut self, cycle: &[u32], time: f64) -> Option<u32> {
+        let next = |id: u32| cycle[(cycle.iter().position(|&c| c == id).unwrap_or(0) + 1) % cycle.len()];
+        let mut held: Vec<(&Car, Crossing, f64)> = self.cars.iter()
+            .filter(|c| cycle.contains(&c.id()) && c.is_held() && c.following() != Some(next(c.id())))
+            .filter(|c| !self.blocked.contains_key(&c.id()))
+            .filter_map(|c| Some((c, c.next_crossing()?, *self.stopped_since.get(&c.id())?)))
+            .filter(|(c, crossing, _)| stopped_at_line(c, crossing))
+            .collect();
+        held.sort_by(|a, b| a.2.total_cmp(&b.2));
+        let mut controllers = std::mem::take(&mut self.controllers);
+        let released = held.into_iter()
+            .find(|(car, crossing, _)| controllers[crossing.node].give_priority(car, crossing, &self.context(crossing.node, time)))
+            .map(|(car, _, _)| car.id());
+        self.controllers = controllers;
+        released
+    }
+
+    /// Every deadlock found so far.
+    pub fn deadlocks(&self) -> &[Deadlock] {
+        &self.deadlocks
+    }
+
+    /// The deadlock that failed the run. The run should not go on.
+    pub fn failed(&self) -> Option<&Deadlock> {
+        self.failed.map(|i| &self.deadlocks[i])
+    }
+
+    pub fn cars(&self) -> &[Car] {
+        &self.cars
+    }
+
+    /// Cars on every lane, for light controllers and the HUD.
+    pub fn occupancy(&self) -> &Occupancy {
+        &self.occupancy
+    }
+
+    /// Loops on the approach lanes, read by actuated controllers.
+    pub fn detectors(&self) -> &Detectors {
+        &self.detectors
+    }
+
+    pub fn queues(&self) -> &[EntryQueue] {
+        &self.queues
+    }
+
+    /// Cars asked for that are still waiting to enter the network.
+    pub fn unserved(&self) -> usize {
+        self.queues.iter().map(EntryQueue::len).sum()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Simulated time since the start, in seconds.
+    pub fn time(&self) -> f64 {
+        self.tick as f64 * self.config.dt
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time between two random cars, the way a headless run without demand spawns them.
+    const SPAWN_INTERVAL_S: f64 = 0.45;
+    /// Time between two random cars the grid takes without filling up.
+    const STEADY_INTERVAL_S: f64 = 1.0;
+    const TICKS: u64 = 18000;
+
+    /// Stats half way through a five minute run, and at its end.
+    fn run(network: &str, plan: Option<&str>) -> (Stats, Stats) {
+        let network = Network::load(network).unwrap();
+        let plan = plan.map(|path| SignalPlan::load(path, &network).unwrap());
+        let mut sim = Simulation::new(Config { seed: 7, ..Config::default() }, network);
+        if let Some(plan) = plan {
+            sim.set_signal_plan(&plan).unwrap();
+        }
+        let every = (STEADY_INTERVAL_S / sim.config().dt).round() as u64;
+        let mut half = None;
+        while sim.tick() < TICKS {
+            assert!(sim.failed().is_none(), "run aborted at {:.1} s", sim.time());
+            if sim.tick().is_multiple_of(every) {
+                sim.spawn_random_car();
+            }
+            sim.step();
+            if sim.tick() == TICKS / 2 {
+                half = Some(sim.stats());
+            }
+        }
+        (half.unwrap(), sim.stats())
+    }
+
+    /// No collision, and cars keep getting through in the second half of the run.
+    fn assert_flowing((half, end): (Stats, Stats)) {
+        assert_eq!(end.collisions, 0);
+        assert!(half.passed > 0);
+        assert!(end.passed - half.passed >= half.passed / 2, "{} cars passed by half time, {} by the end", half.passed, end.passed);
+    }
+
+    #[test]
+    fn signalised_grid_keeps_flowing() {
+        assert_flowing(run("assets/grid.toml", None));
+    }
+
+    #[test]
+    fn signalised_corridor_keeps_flowing() {
+        for plan in [None, Some("assets/green_wave.toml")] {
+            assert_flowing(run("assets/corridor.toml", plan));
+        }
+    }
+
+    #[test]
+    fn gridlock_fails_the_run() {
+        // The grid saturates at this rate, and cars end up in a ring around a block
+        let network = Network::load("assets/grid.toml").unwrap();
+        let mut sim = Simulation::new(Config { seed: 1, mode: Mode::Reservation, ..Config::default() }, network);
+        let every = (SPAWN_INTERVAL_S / sim.config().dt).round() as u64;
+        while sim.time() < 120.0 && sim.failed().is_none() {
+            if sim.tick().is_multiple_of(every) {
+                sim.spawn_random_car();
+            }
+            sim.step();
+        }
+        let failed = sim.failed().expect("the gridlock stalls the run without failing it");
+        assert_eq!(failed.released, None);
+        assert_eq!(sim.deadlocks().last().unwrap().cars, failed.cars);
+    }
+
+    /// The single four-way junction, with a car asked for from `from` to the opposite side
+    /// `cars` times in a row.
+    fn queued(config: Config, from: Direction, cars: usize) -> Simulation {
+        let network = Network::single(map::Layout::load(map::DEFAULT_LAYOUT).unwrap());
+        let mut sim = Simulation::new(config, network);
+        let entry = Port { node: 0, side: from };
+        let exit = Port { node: 0, side: from.opposite() };
+        for _ in 0..cars {
+            assert!(sim.spawn_car(entry, exit));
+        }
+        sim
+    }
+
+    fn run_for(sim: &mut Simulation, seconds: f64) {
+        let end = sim.time() + seconds;
+        while sim.time() < end {
+            sim.step();
+        }
+    }
+
+    #[test]
+    fn queue_holds_cars_until_the_entry_is_clear() {
+        let mut sim = queued(Config { seed: 1, ..Config::default() }, Direction::North, 3);
+        assert_eq!(sim.unserved(), 3);
+        assert_eq!(sim.stats().spawned, 0);
+        sim.step();
+        assert_eq!(sim.stats().spawned, 1);
+        assert_eq!(sim.unserved(), 2);
+        // The first car has not cleared the spawn point yet
+        sim.step();
+        assert_eq!(sim.stats().spawned, 1);
+        run_for(&mut sim, 5.0);
+        assert_eq!(sim.unserved(), 0);
+    }
+
+    #[test]
+    fn queue_releases_cars_in_order() {
+        let mut sim = queued(Config { seed: 1, ..Config::default() }, Direction::North, 3);
+        run_for(&mut sim, 5.0);
+        let ids: Vec<u32> = sim.cars().iter().map(Car::id).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        let along: Vec<f64> = sim.cars().iter().map(Car::travelled).collect();
+        assert!(along.windows(2).all(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    fn queue_holds_cars_while_their_lane_is_full() {
+        // The east approach is on red for the first five seconds: the cars line up on it
+        let lane = LaneId::Approach { light: 2, lane: 0 };
+        let mut sim = queued(Config { seed: 1, lane_cap: 2, ..Config::default() }, Direction::East, 4);
+        run_for(&mut sim, 4.5);
+        assert_eq!(sim.occupancy().count(lane), 2);
+        assert!(sim.occupancy().is_full(lane));
+        assert_eq!(sim.unserved(), 2);
+
+        let mut sim = queued(Config { seed: 1, ..Config::default() }, Direction::East, 4);
+        run_for(&mut sim, 4.5);
+        assert_eq!(sim.occupancy().count(lane), 4);
+        assert_eq!(sim.unserved(), 0);
+    }
+
+    #[test]
+    fn lane_cap_is_never_exceeded() {
+        let network = Network::single(map::Layout::load(map::DEFAULT_LAYOUT).unwrap());
+        let mut sim = Simulation::new(Config { seed: 5, lane_cap: 3, ..Config::default() }, network);
+        for tick in 0..3600 {
+            if tick % 10 == 0 {
+                sim.spawn_random_car();
+            }
+            sim.step();
+            assert!(sim.occupancy().lanes().all(|(_, cars)| cars.len() <= 3));
+        }
+        assert!(sim.unserved() > 0);
+    }
+}